@@ -0,0 +1,113 @@
+use eframe::egui::{ self, TextureHandle, TextureOptions };
+
+use crate::heatmap::{ self, TileGrid };
+use crate::neural_net::NeuralNet;
+use crate::neural_net::dense::Dense;
+use crate::neural_net::conv::{ Conv2D, Shape };
+use crate::neural_net::pooling::{ AvgPool2D, MaxPool2D };
+
+use std::iter;
+
+// the input is an MNIST image.
+const INPUT_SHAPE: Shape = Shape { channels: 1, height: 28, width: 28 };
+
+// values that aren't images are drawn as a strip of cells, this many to a row.
+const CELLS_PER_ROW: usize = 32;
+const CELL_SIZE: usize = 4;
+
+// how many pixels wide each pixel of an image is drawn, at most.
+const MAX_ZOOM: f32 = 4.0;
+
+/// The output of every layer of a net for one input: images (one tile per channel) where
+/// the layer keeps the shape of one, strips of cells otherwise. Each layer is coloured on
+/// its own scale.
+#[derive(Default)]
+pub struct ActivationsView {
+    textures: Vec<TextureHandle>,
+}
+
+impl ActivationsView {
+    pub fn show(&mut self, ui: &mut egui::Ui, nn: &NeuralNet, input: &[f32]) {
+        let activations = nn.nn_process_forward(input.to_vec());
+        let names: Vec<String> = iter::once("Input".to_string())
+            .chain(nn.layers().layers().iter().map(|layer| layer.name()))
+            .collect();
+
+        self.textures.truncate(activations.len());
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (i, ((values, name), shape)) in activations.iter().zip(names).zip(output_shapes(nn)).enumerate() {
+                let largest = values.iter().fold(f32::MIN_POSITIVE, |largest, x| largest.max(x.abs()));
+                ui.label(format!("{name} (largest {largest:.3})"));
+
+                let (grid, cells): (TileGrid, Vec<Vec<egui::Color32>>) = match shape {
+                    Some(shape) => (
+                        TileGrid { tile_size: [shape.width, shape.height], columns: shape.channels.min(8), count: shape.channels },
+                        values.chunks(shape.width * shape.height)
+                            .map(|channel| channel.iter().map(|x| heatmap::diverging_color(x / largest)).collect())
+                            .collect(),
+                    ),
+                    None => (
+                        TileGrid { tile_size: [CELL_SIZE, CELL_SIZE], columns: values.len().min(CELLS_PER_ROW), count: values.len() },
+                        values.iter()
+                            .map(|x| vec![heatmap::diverging_color(x / largest); CELL_SIZE * CELL_SIZE])
+                            .collect(),
+                    ),
+                };
+
+                let image = grid.image(cells);
+                let [width, height] = image.size.map(|size| size as f32);
+                if let Some(texture) = self.textures.get_mut(i) {
+                    texture.set(image, TextureOptions::NEAREST);
+                } else {
+                    self.textures.push(ui.ctx().load_texture(format!("activations {i}"), image, TextureOptions::NEAREST));
+                }
+
+                let zoom = (ui.available_width() / width).min(MAX_ZOOM);
+                let response = ui.add(egui::Image::from_texture(&self.textures[i])
+                    .fit_to_exact_size(egui::vec2(width, height) * zoom)
+                    .sense(egui::Sense::hover()));
+
+                let tile = response.hover_pos().and_then(|pos| {
+                    let at = (pos - response.rect.min) / response.rect.size();
+                    grid.tile_at([at.x, at.y])
+                });
+                if let Some(tile) = tile {
+                    let text = match shape {
+                        Some(shape) => {
+                            let channel = &values[tile * shape.width * shape.height..][..shape.width * shape.height];
+                            let (min, max) = channel.iter().fold((f32::MAX, f32::MIN), |(min, max), &x| (min.min(x), max.max(x)));
+                            format!("channel {tile}\nvalues {min:.3} to {max:.3}")
+                        }
+                        None => format!("unit {tile}\nvalue {:.3}", values[tile]),
+                    };
+                    response.on_hover_text_at_pointer(text);
+                }
+            }
+        });
+    }
+}
+
+// the shape of the input and of each layer's output, where it's still an image.
+fn output_shapes(nn: &NeuralNet) -> Vec<Option<Shape>> {
+    let mut shape = Some(INPUT_SHAPE);
+    let mut shapes = vec![shape];
+
+    for layer in nn.layers().layers() {
+        let layer = layer.as_any();
+        shape = if let Some(conv) = layer.downcast_ref::<Conv2D>() {
+            Some(conv.output_shape())
+        } else if let Some(pool) = layer.downcast_ref::<MaxPool2D>() {
+            Some(pool.output_shape())
+        } else if let Some(pool) = layer.downcast_ref::<AvgPool2D>() {
+            Some(pool.output_shape())
+        } else if layer.is::<Dense>() {
+            None
+        } else {
+            // activations, dropout and batch norm keep the shape of their input.
+            shape
+        };
+        shapes.push(shape);
+    }
+    shapes
+}
@@ -0,0 +1,180 @@
+use eframe::egui::{ self, Color32, ColorImage, TextureHandle, TextureOptions };
+
+use crate::heatmap;
+use crate::neural_net::{ self, NeuralNet, NNData };
+use crate::neural_net::adversarial::{ Attack, AttackReport };
+
+use std::sync::Arc;
+use std::thread::{ self, JoinHandle };
+use std::time::Duration;
+
+const IMAGE_SIZE: [usize; 2] = [28, 28];
+const MAX_IMAGE_WIDTH: f32 = 280.0;
+
+/// Attacks on the net: one test image at a time next to its attacked version, and how often
+/// an attack fools the net over the test set.
+pub struct AttackView {
+    attack: Attack,
+    index: usize,
+    /// How many test samples the success rate is measured on.
+    samples: usize,
+    textures: Vec<TextureHandle>,
+    captions: Vec<String>,
+    // what `textures` and `captions` show, so the attack is only run again when that changes.
+    shown: Option<Shown>,
+    report: Option<AttackReport>,
+    // measuring over the test set takes too long for one frame.
+    measuring: Option<JoinHandle<AttackReport>>,
+}
+
+struct Shown {
+    index: usize,
+    attack: Attack,
+    nn_version: u64,
+}
+
+impl Default for AttackView {
+    fn default() -> Self {
+        Self {
+            attack: Attack::ALL[0],
+            index: 0,
+            samples: 1000,
+            textures: Vec::new(),
+            captions: Vec::new(),
+            shown: None,
+            report: None,
+            measuring: None,
+        }
+    }
+}
+
+impl AttackView {
+    /// `nn_version` changes whenever `nn` does. While `training` runs, a new snapshot of the
+    /// net every few frames would mean attacking the image again on the GUI thread each time,
+    /// so then the attack only follows the image and attack picked.
+    pub fn show(&mut self, ui: &mut egui::Ui, nn: &NeuralNet, nn_version: u64, training: bool, test_data: &Arc<Vec<NNData>>) {
+        self.controls(ui);
+
+        let outdated = match &self.shown {
+            Some(shown) => shown.index != self.index || shown.attack != self.attack || (!training && shown.nn_version != nn_version),
+            None => true,
+        };
+        if outdated {
+            self.draw(ui, nn, &test_data[self.index]);
+            self.shown = Some(Shown { index: self.index, attack: self.attack, nn_version });
+        }
+
+        let width = ((ui.available_width() - 2.0 * ui.spacing().item_spacing.x) / 3.0).min(MAX_IMAGE_WIDTH);
+        ui.horizontal_top(|ui| {
+            for (texture, caption) in self.textures.iter().zip(&self.captions) {
+                ui.vertical(|ui| {
+                    ui.add(egui::Image::from_texture(texture).fit_to_exact_size(egui::vec2(width, width)));
+                    ui.label(caption);
+                });
+            }
+        });
+
+        if self.shown.as_ref().is_some_and(|shown| shown.nn_version != nn_version) {
+            ui.label("Attacked with the weights from before training went on; it's attacked again once training pauses.");
+        }
+
+        ui.add(egui::Slider::new(&mut self.index, 0..=test_data.len() - 1)
+            .clamping(egui::SliderClamping::Edits)
+            .text("Test Image ID"));
+
+        self.success_rate(ui, nn, test_data);
+    }
+
+    // attacks `data_point`, and draws it before and after.
+    fn draw(&mut self, ui: &egui::Ui, nn: &NeuralNet, data_point: &NNData) {
+        let original = neural_net::scale_and_normalize_data(&data_point.data);
+        let adversarial = nn.attack(data_point, self.attack);
+        let epsilon = self.attack.epsilon().max(f32::MIN_POSITIVE);
+
+        let images = [
+            original.iter().map(|&x| pixel_color(x)).collect(),
+            adversarial.iter().zip(&original).map(|(x, original)| heatmap::diverging_color((x - original) / epsilon)).collect(),
+            adversarial.iter().map(|&x| pixel_color(x)).collect(),
+        ];
+        self.captions = vec![
+            format!("original, label {}\n{}", data_point.label, prediction_text(nn, &original)),
+            format!("perturbation (±{})\nred adds ink, blue takes it away", self.attack.epsilon()),
+            format!("adversarial\n{}", prediction_text(nn, &adversarial)),
+        ];
+
+        for (i, pixels) in images.into_iter().enumerate() {
+            let image = ColorImage { size: IMAGE_SIZE, pixels };
+            if let Some(texture) = self.textures.get_mut(i) {
+                texture.set(image, TextureOptions::NEAREST);
+            } else {
+                self.textures.push(ui.ctx().load_texture(format!("attack {i}"), image, TextureOptions::NEAREST));
+            }
+        }
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal_top(|ui| {
+            egui::ComboBox::from_label("Attack")
+                .selected_text(self.attack.name())
+                .show_ui(ui, |ui| {
+                    for attack in Attack::ALL {
+                        let selected = self.attack.name() == attack.name();
+                        if ui.selectable_label(selected, attack.name()).clicked() && !selected {
+                            self.attack = attack;
+                        }
+                    }
+                });
+
+            match &mut self.attack {
+                Attack::Fgsm { epsilon } => {
+                    ui.add(egui::DragValue::new(epsilon).range(0.0..=1.0).speed(0.001).prefix("ε: "));
+                }
+                Attack::Pgd { epsilon, step_size, steps } => {
+                    ui.add(egui::DragValue::new(epsilon).range(0.0..=1.0).speed(0.001).prefix("ε: "));
+                    ui.add(egui::DragValue::new(step_size).range(0.0001..=1.0).speed(0.0005).prefix("step size: "));
+                    ui.add(egui::DragValue::new(steps).range(1..=200).prefix("steps: "));
+                }
+            }
+        });
+    }
+
+    fn success_rate(&mut self, ui: &mut egui::Ui, nn: &NeuralNet, test_data: &Arc<Vec<NNData>>) {
+        if self.measuring.as_ref().is_some_and(JoinHandle::is_finished) {
+            self.report = self.measuring.take().unwrap().join().ok();
+        }
+
+        ui.horizontal_top(|ui| {
+            ui.add(egui::DragValue::new(&mut self.samples)
+                .range(1..=test_data.len())
+                .speed(10)
+                .prefix("Measure on ")
+                .suffix(" test samples"));
+
+            if self.measuring.is_some() {
+                ui.spinner();
+                // nothing else would wake the GUI up when it's done.
+                ui.ctx().request_repaint_after(Duration::from_millis(100));
+            } else if ui.button("Measure Success Rate").clicked() {
+                // on a copy of the weights, so training can carry on.
+                let (nn, data, attack, samples) = (nn.clone(), Arc::clone(test_data), self.attack, self.samples);
+                self.measuring = Some(thread::spawn(move || nn.attack_success_rate(&data[..samples.min(data.len())], attack)));
+            }
+        });
+
+        if let Some(report) = &self.report {
+            ui.label(report.to_string());
+        }
+    }
+}
+
+// the inverse of `scale_and_normalize_data`, drawn like the Inspect Data view draws pixels.
+fn pixel_color(x: f32) -> Color32 {
+    let value = ((x - 0.01) / 0.98 * 255.0).round().clamp(0.0, 255.0) as u8;
+    Color32::from_gray(255 - value)
+}
+
+fn prediction_text(nn: &NeuralNet, input: &[f32]) -> String {
+    let output = nn.image_to_prediction(input.to_vec());
+    let prediction = neural_net::argmax(&output);
+    format!("predicted {prediction} ({:.3})", output[prediction])
+}
@@ -81,7 +81,7 @@ impl Canvas {
                                 self.paint_color.b(),
                                 (intensity * 255.0) as u8,
                             );
-                            *pixel = pixel.clone().blend(pix_value);
+                            *pixel = pixel.blend(pix_value);
                         }
                     }
                 }
@@ -91,12 +91,8 @@ impl Canvas {
     }
     
     pub fn draw_line(&mut self, start: Vec2, end: Vec2) -> Result<(), CanvasError> {
-        if let Err(e) = self.check_point_bounds(start) {
-            return Err(e);
-        }
-        if let Err(e) = self.check_point_bounds(end) {
-            return Err(e);
-        }
+        self.check_point_bounds(start)?;
+        self.check_point_bounds(end)?;
 
         let line_length = (end - start).length();
         
@@ -107,7 +103,7 @@ impl Canvas {
 
         let grad = vec2((end.x - start.x) / line_length, (end.y - start.y) / line_length);
 
-        let mut pos = start.clone();
+        let mut pos = start;
 
         pos += grad;
         loop {
@@ -39,11 +39,10 @@ pub fn get_mnist_images(image_path: &str, label_path: &str) -> Result<(Vec<Vec<u
 }
 
 // returns dimension count, dimension sizes.
-fn parse_idx_meta(data: &Vec<u8>) -> (u8, Vec<u32>) {
-    let dimension_count = data[3].clone();
+fn parse_idx_meta(data: &[u8]) -> (u8, Vec<u32>) {
+    let dimension_count = data[3];
 
-    let mut dimension_sizes: Vec<u32> = Vec::new(); 
-    dimension_sizes.reserve(dimension_count as usize);
+    let mut dimension_sizes: Vec<u32> = Vec::with_capacity(dimension_count as usize);
 
     for i in 0..dimension_count as usize {
         dimension_sizes.push(u32::from_be_bytes(
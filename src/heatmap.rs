@@ -0,0 +1,63 @@
+use eframe::egui::{ Color32, ColorImage };
+
+// ends of the diverging colour map, from ColorBrewer's RdBu.
+const NEGATIVE: [f32; 3] = [33.0, 102.0, 172.0];
+const POSITIVE: [f32; 3] = [178.0, 24.0, 43.0];
+
+const GAP_COLOR: Color32 = Color32::from_gray(64);
+
+/// Blue for negative values, white for 0 and red for positive ones; `value` is clamped to
+/// `[-1, 1]`.
+pub fn diverging_color(value: f32) -> Color32 {
+    let value = value.clamp(-1.0, 1.0);
+    let end = if value < 0.0 { NEGATIVE } else { POSITIVE };
+    let [r, g, b] = end.map(|channel| (255.0 + (channel - 255.0) * value.abs()).round() as u8);
+    Color32::from_rgb(r, g, b)
+}
+
+/// Equally sized images laid out in rows, a pixel apart, to be drawn as one image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileGrid {
+    pub tile_size: [usize; 2],
+    pub columns: usize,
+    pub count: usize,
+}
+
+impl TileGrid {
+    fn rows(&self) -> usize {
+        self.count.div_ceil(self.columns)
+    }
+
+    pub fn image_size(&self) -> [usize; 2] {
+        let [width, height] = self.tile_size;
+        [self.columns * (width + 1) - 1, (self.rows() * (height + 1)).max(1) - 1]
+    }
+
+    /// One image of all the tiles; each is its pixels row by row.
+    pub fn image(&self, tiles: impl IntoIterator<Item = Vec<Color32>>) -> ColorImage {
+        let [width, height] = self.tile_size;
+        let size = self.image_size();
+        let mut image = ColorImage::new(size, GAP_COLOR);
+
+        for (i, tile) in tiles.into_iter().enumerate().take(self.count) {
+            let (left, top) = ((i % self.columns) * (width + 1), (i / self.columns) * (height + 1));
+            for (j, color) in tile.into_iter().enumerate().take(width * height) {
+                image.pixels[(top + j / width) * size[0] + left + j % width] = color;
+            }
+        }
+        image
+    }
+
+    /// The tile at a point of the image, given as fractions of its width and height.
+    pub fn tile_at(&self, [x, y]: [f32; 2]) -> Option<usize> {
+        let [width, height] = self.image_size();
+        if !(0.0..1.0).contains(&x) || !(0.0..1.0).contains(&y) {
+            return None;
+        }
+
+        let column = (x * width as f32) as usize / (self.tile_size[0] + 1);
+        let row = (y * height as f32) as usize / (self.tile_size[1] + 1);
+        let tile = row * self.columns + column;
+        (tile < self.count).then_some(tile)
+    }
+}
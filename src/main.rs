@@ -1,12 +1,18 @@
 
+mod activations_view;
+mod attack_view;
 mod canvas;
 mod neural_net;
 mod data_reader;
+mod heatmap;
+mod model_builder;
+mod saliency_view;
+mod train_plot;
+mod training;
+mod weights_view;
 
 use eframe::egui;
 
-use egui_plot::{Plot, Line, PlotPoints};
-
 use egui::{
     widgets,
     Vec2,
@@ -18,11 +24,25 @@ use egui::{
     TextureOptions,
 };
 
+use activations_view::ActivationsView;
+use attack_view::AttackView;
 use canvas::Canvas;
+use model_builder::ModelBuilder;
+use saliency_view::SaliencyOverlay;
+use train_plot::TrainPlot;
+use weights_view::WeightsView;
+use training::{ Training, TrainingOptions, TrainingState, NetSettings, Message, StopReason };
 use neural_net::{ NeuralNet, NNData };
+use neural_net::activation::Activation;
+use neural_net::loss::Loss;
+use neural_net::init::Initialization;
+use neural_net::optimizer::Optimizer;
+use neural_net::health::NonFinite;
+use neural_net::history::History;
+use neural_net::gradient_check::GradientCheckReport;
+use neural_net::validation::{ self, Evaluation, EarlyStopping, EarlyStop, Metric };
 
 use std::sync::{Arc, RwLock};
-use std::sync::mpsc::{self, TryRecvError, Sender};
 use std::thread;
 use std::iter::zip;
 
@@ -39,10 +59,21 @@ fn main() -> Result<(), eframe::Error> {
     )
 }
 
+const MODEL_PATH: &str = "./model.nn";
+
+// held out of the MNIST training set, to see how the net does on images it isn't trained on.
+const VALIDATION_FRACTION: f32 = 1.0 / 12.0;
+
+// buckets kept at each resolution of the training loss history.
+const HISTORY_CAPACITY: usize = 2048;
+
 enum View {
     Draw,
     Train,
+    Model,
+    Weights,
     InspectData,
+    Attack,
 }
 
 struct MyApp {
@@ -54,37 +85,69 @@ struct MyApp {
     data_view_texture: Option<TextureHandle>,
     data_view_index: usize,
     training_data: Arc<Vec<neural_net::NNData>>,
-    //testing_data: Arc<Vec<neural_net::NNData>>,
-    error_data: Arc<RwLock<Vec<f32>>>,
-    nn: Arc<RwLock<NeuralNet>>,
-    learning_rate: f32,
-    training_thread_tx: Option<Sender<()>>,
+    validation_data: Arc<Vec<neural_net::NNData>>,
+    testing_data: Arc<Vec<neural_net::NNData>>,
+    loss_data: History,
+    // (training samples seen, evaluation on the validation split).
+    validation_history: Vec<(usize, Evaluation)>,
+    validation_interval: usize,
+    early_stopping_enabled: bool,
+    early_stopping: EarlyStopping,
+    early_stop: Option<EarlyStop>,
+    train_plot: TrainPlot,
+    weights_view: WeightsView,
+    attack_view: AttackView,
+    numerical_error: Option<NonFinite>,
+    // why the last run ended, if it broke down rather than being stopped.
+    training_failure: Option<String>,
+    // the GUI's own copy; while training, it's replaced by each snapshot of the net being trained.
+    nn: NeuralNet,
+    // counts the changes to `nn`, so the views can tell when what they worked out from it is stale.
+    nn_version: u64,
+    settings: NetSettings,
+    // the settings last pushed onto `nn`.
+    applied_settings: NetSettings,
+    // the layers of the next net to build.
+    model_builder: ModelBuilder,
+    training_threads: usize,
+    batch_size: usize,
+    hogwild: bool,
+    seed: u64,
+    // the seed of the net the last run started from, and the one its batches came from.
+    run_seeds: Option<(u64, u64)>,
+    gradient_check: Option<GradientCheckReport>,
+    model_file_status: Option<String>,
+    training: Option<Training>,
+    // batches trained by "Train Steps".
+    train_steps: usize,
 
     drawing_data: Arc<RwLock<Canvas>>,
+    activations_view: ActivationsView,
+    // shared by the Draw and Inspect Data views.
+    saliency: SaliencyOverlay,
     prev_brush_pos: Option<Vec2>,
     view: View,
 }
 
 impl MyApp {
     fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        let error_data = Arc::new(RwLock::new(Vec::new()));
-
         let ctx = Arc::new(cc.egui_ctx.clone());
 
 
         let (training_images, training_labels) = data_reader::get_mnist_images("./data/train-images.idx3-ubyte", "./data/train-labels.idx1-ubyte").unwrap();
 
-        //let (testing_images, testing_labels) = data_reader::get_mnist_images("./data/t10k-images.idx3-ubyte", "./data/t10k-labels.idx1-ubyte").unwrap();
+        let (testing_images, testing_labels) = data_reader::get_mnist_images("./data/t10k-images.idx3-ubyte", "./data/t10k-labels.idx1-ubyte").unwrap();
 
+        let seed = rand::random();
         let mut nn = NeuralNet::new();
 
-        nn.populate_random_weights();
+        nn.populate_seeded_weights(seed);
 
-        let training_data = Arc::new(zip(training_images, training_labels)
-            .map(|(data, label)| NNData { data, label: label as usize }).collect());
+        let (training_data, validation_data) = validation::split_validation(zip(training_images, training_labels)
+            .map(|(data, label)| NNData { data, label: label as usize }).collect(), VALIDATION_FRACTION, seed);
 
-        //let testing_data = Arc::new(zip(testing_images, testing_labels)
-        //    .map(|(data, label)| NNData { data, label: label as usize }).collect());
+        let testing_data = Arc::new(zip(testing_images, testing_labels)
+            .map(|(data, label)| NNData { data, label: label as usize }).collect());
     
         Self {
             ctx,
@@ -94,21 +157,157 @@ impl MyApp {
 
             data_view_texture: None,
             data_view_index: 0,
-            training_data,
-            //testing_data,
-            error_data,
-            nn: Arc::new(RwLock::new(nn)),
-            learning_rate: 0.1,
-            training_thread_tx: None,
-            
+            training_data: Arc::new(training_data),
+            validation_data: Arc::new(validation_data),
+            testing_data,
+            loss_data: History::new(HISTORY_CAPACITY),
+            validation_history: Vec::new(),
+            validation_interval: 10_000,
+            early_stopping_enabled: false,
+            early_stopping: EarlyStopping::default(),
+            early_stop: None,
+            numerical_error: None,
+            training_failure: None,
+            train_plot: TrainPlot::default(),
+            weights_view: WeightsView::default(),
+            attack_view: AttackView::default(),
+            nn,
+            nn_version: 0,
+            settings: NetSettings::default(),
+            applied_settings: NetSettings::default(),
+            model_builder: ModelBuilder::default(),
+            training_threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            batch_size: 8,
+            hogwild: false,
+            seed,
+            run_seeds: None,
+            gradient_check: None,
+            model_file_status: None,
+            training: None,
+            train_steps: 100,
+
 
             drawing_data: Arc::new(RwLock::new(Canvas::new(Color32::WHITE, Color32::BLACK, [28, 28]))),
 
+            activations_view: ActivationsView::default(),
+            saliency: SaliencyOverlay::default(),
             prev_brush_pos: None,
             view: View::Draw,
         }
     }
 
+    fn replace_nn(&mut self, nn: NeuralNet) {
+        self.nn = nn;
+        self.nn_version += 1;
+    }
+
+    // the Train view pushes its settings onto the net every frame, so they have to match
+    // a loaded net or they'd overwrite it.
+    fn load_settings_from(&mut self, nn: &NeuralNet) {
+        self.settings.read_from(nn);
+        self.model_builder.read_from(nn);
+        self.seed = nn.seed();
+    }
+
+    fn training_state(&self) -> TrainingState {
+        self.training.as_ref().map_or(TrainingState::Idle, Training::state)
+    }
+
+    // starts a training thread, unless there's one already; it pauses after `budget` samples
+    // if there is one.
+    fn start_training(&mut self, budget: Option<usize>) {
+        if self.training.is_some() {
+            return;
+        }
+
+        // batches and dropout masks follow from the run's seed, so a run can be repeated.
+        let trainer_seed = self.nn.next_seed();
+        self.run_seeds = Some((self.nn.seed(), trainer_seed));
+
+        self.early_stop = None;
+        self.numerical_error = None;
+        self.training_failure = None;
+
+        let options = TrainingOptions {
+            threads: self.training_threads,
+            batch_size: self.batch_size,
+            hogwild: self.hogwild,
+            seed: trainer_seed,
+            validation_interval: self.validation_interval,
+            early_stopping: self.early_stopping_enabled.then_some(self.early_stopping),
+            samples: self.loss_data.len(),
+            budget,
+        };
+        self.training = Some(Training::start(
+            self.nn.clone(),
+            self.settings,
+            Arc::clone(&self.training_data),
+            Arc::clone(&self.validation_data),
+            options,
+            Arc::clone(&self.ctx)));
+    }
+
+    // trains `samples` more samples, then pauses; from idle, it starts a new run to do it.
+    fn train_samples(&mut self, samples: usize) {
+        match &mut self.training {
+            Some(training) => training.train(samples),
+            None => self.start_training(Some(samples)),
+        }
+    }
+
+    // takes in whatever the training thread has sent since the last frame.
+    fn receive_training_messages(&mut self) {
+        let Some(training) = &mut self.training else {
+            return;
+        };
+
+        // checked first, so if it's done everything it sent is drained below.
+        let thread_ended = training.is_finished();
+        let mut finished = false;
+
+        for message in training.messages() {
+            match message {
+                // the `Training` keeps track of its state itself.
+                Message::State(TrainingState::Running) => self.numerical_error = None,
+                Message::State(_) => {}
+                Message::NumericalError(problem) => self.numerical_error = Some(problem),
+                Message::Losses(losses) => self.loss_data.extend(losses),
+                Message::Validation(samples, evaluation) => self.validation_history.push((samples, evaluation)),
+                Message::Snapshot(nn) => self.replace_nn(*nn),
+                Message::Finished(reason) => {
+                    finished = true;
+                    match reason {
+                        StopReason::EarlyStop(stop) => self.early_stop = Some(stop),
+                        StopReason::Stopped => {}
+                        StopReason::WorkersExited => self.training_failure = Some(reason.to_string()),
+                    }
+                }
+            }
+        }
+
+        if finished || thread_ended {
+            if !finished {
+                self.training_failure = Some("the training thread ended unexpectedly".to_string());
+            }
+            self.training = None;
+        }
+    }
+
+    // the drawing as the net's input.
+    fn drawing_input(&self) -> Vec<f32> {
+        neural_net::scale_and_normalize_data(
+            &self.drawing_data.read().unwrap().get_pixels_as_slice()
+            .iter()
+            .map(|color|
+                (255.0 - (
+                    color.r() as f32 +
+                    color.g() as f32 +
+                    color.b() as f32
+                ) / (3.0)) as u8
+            ).collect::<Vec<u8>>()
+        )
+    }
+
     fn update_drawing(&mut self, ctx: &egui::Context) {
 
         let canvas = self.drawing_data.write().unwrap();
@@ -136,6 +335,8 @@ impl eframe::App for MyApp {
             style.spacing.item_spacing = vec2(5.0, 5.0);
         });
 
+        self.receive_training_messages();
+
 
         egui::SidePanel::left("output values")
             .resizable(false)
@@ -153,6 +354,16 @@ impl eframe::App for MyApp {
             });
         });
 
+        // follows the drawing (and the weights) every frame, like the prediction.
+        if matches!(self.view, View::Draw) {
+            egui::SidePanel::right("layer activations")
+                .default_width(320.0)
+                .show(ctx, |ui| {
+                    let input = self.drawing_input();
+                    self.activations_view.show(ui, &self.nn, &input);
+                });
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
 
             ui.horizontal_top(|ui| {
@@ -165,106 +376,373 @@ impl eframe::App for MyApp {
                     self.view = View::Train;
                 }
 
+                if ui.button("Model").clicked() {
+                    self.view = View::Model;
+                }
+
+                if ui.button("Weights").clicked() {
+                    self.view = View::Weights;
+                }
+
                 if ui.button("Inspect Data").clicked() {
                     self.view = View::InspectData;
                 }
+
+                if ui.button("Inspect Attacks").clicked() {
+                    self.view = View::Attack;
+                }
             });
 
+            // the Train and Model views both edit the settings.
+            self.settings.apply(&mut self.nn);
+            if self.settings != self.applied_settings {
+                self.applied_settings = self.settings;
+                self.nn_version += 1;
+            }
+            if let Some(training) = &mut self.training {
+                training.update_settings(self.settings);
+            }
+
             match self.view {
                 View::Train => {
-                    //let sin: PlotPoints = (0..1000).map(|i| {
-                    //    let x = i as f64 * 0.01;
-                    //    [x, x.sin()]
-                    //}).collect();
-
-                    //let line = Line::new(sin);
-
-                    let line;
-                    {
-                        loop {
-                            //if let Some(tx) = self.training_thread_tx.as_ref() {
-                            //    if let Ok(_) = tx.send(true) {
-                            //        thread::sleep(Duration::from_millis(3));
-                            //    }
-                            //}
-                                    
-
-                            let mut i = 0;
-                            if let Ok(data_with_lock) = self.error_data.try_read() {
-
-                                let error_points: PlotPoints = data_with_lock.iter()
-                                    .map(|y| {
-                                        let out = [i as f64, *y as f64];
-                                        i += 1;
-                                        out
-                                    })
-                                    .collect();
-                                line = Line::new(error_points);
-                                break;
+                    self.train_plot.show(ui, &self.loss_data, &self.validation_history, self.training_data.len());
+                    self.train_plot.controls(ui);
+
+                    let state = self.training_state();
+                    ui.horizontal_top(|ui| {
+                        ui.label(format!("Training: {}", state.name()));
+                        if let Some((seed, trainer_seed)) = self.run_seeds {
+                            ui.label(format!("(run seed {seed}, trainer seed {trainer_seed})"));
+                        }
+
+                        match state {
+                            TrainingState::Idle => {
+                                if ui.button("Start Training").clicked() {
+                                    self.start_training(None);
+                                }
                             }
+                            TrainingState::Running => {
+                                if ui.button("Pause").clicked() {
+                                    self.training.as_ref().unwrap().pause();
+                                }
+                            }
+                            TrainingState::Paused => {
+                                if ui.button("Resume").clicked() {
+                                    self.training.as_mut().unwrap().resume();
+                                }
+                            }
+                            TrainingState::Stopping => {}
+                        }
+
+                        // stepping only makes sense from a standstill.
+                        let standstill = matches!(state, TrainingState::Idle | TrainingState::Paused);
+                        ui.add_enabled(standstill, egui::DragValue::new(&mut self.train_steps)
+                            .range(1..=1_000_000)
+                            .suffix(" steps"));
+                        if ui.add_enabled(standstill, egui::Button::new("Train Steps")).clicked() {
+                            // hogwild workers each take batches, so their steps are counted in samples too.
+                            self.train_samples(self.train_steps * self.batch_size);
                         }
+                        if ui.add_enabled(standstill, egui::Button::new("Train 1 Epoch")).clicked() {
+                            self.train_samples(self.training_data.len());
+                        }
+
+                        let stoppable = matches!(state, TrainingState::Running | TrainingState::Paused);
+                        if ui.add_enabled(stoppable, egui::Button::new("Stop Training")).clicked() {
+                            self.training.as_mut().unwrap().stop();
+                        }
+                    });
+
+                    ui.add(egui::Slider::new(&mut self.settings.learning_rate, 0.0001..=0.4)
+                        .clamping(egui::SliderClamping::Edits)
+                        .text("Learning Rate")
+                    );
+
+                    // only picked up when training is (re)started.
+                    ui.add(egui::Slider::new(&mut self.training_threads, 1..=32)
+                        .text("Training Threads")
+                    );
+
+                    ui.checkbox(&mut self.hogwild, "Hogwild (asynchronous)");
+
+                    // only picked up when training is (re)started.
+                    ui.horizontal_top(|ui| {
+                        ui.add(egui::DragValue::new(&mut self.validation_interval)
+                            .range(100..=1_000_000)
+                            .speed(100)
+                            .prefix("Validate every ")
+                            .suffix(" samples"));
+
+                        ui.checkbox(&mut self.early_stopping_enabled, "Early Stopping on");
+
+                        egui::ComboBox::from_id_salt("early stopping metric")
+                            .selected_text(self.early_stopping.metric.name())
+                            .show_ui(ui, |ui| {
+                                for metric in Metric::ALL {
+                                    ui.selectable_value(&mut self.early_stopping.metric, metric, metric.name());
+                                }
+                            });
+
+                        ui.add(egui::DragValue::new(&mut self.early_stopping.patience).range(1..=100).prefix("patience: "));
+                        ui.add(egui::DragValue::new(&mut self.early_stopping.min_delta).range(0.0..=1.0).speed(0.0001).prefix("min delta: "));
+                        ui.checkbox(&mut self.early_stopping.restore_best_weights, "Restore Best Weights");
+                    });
+
+                    if let Some(&(samples, evaluation)) = self.validation_history.last() {
+                        ui.label(format!("Validation after {samples} samples: loss {:.4}, accuracy {:.2}%",
+                            evaluation.loss, 100.0 * evaluation.accuracy));
                     }
-                    
-                    Plot::new("test_plot").view_aspect(2.0).show(ui, |plot_ui| plot_ui.line(line));
-
-                    if ui.button("Start Training").clicked() {
-                        let (tx, rx) = mpsc::channel();
-
-                        self.training_thread_tx = Some(tx);
-
-                        let nn = Arc::clone(&self.nn);
-                        let training_data = Arc::clone(&self.training_data);
-                        let p_points = Arc::clone(&self.error_data);
-
-                        let ctx_arc = Arc::clone(&self.ctx);
-
-                        let _training_thread = thread::spawn(move || {
-                            let mut count = 0;
-                            let mut vals = Vec::new();
-                            vals.reserve(50);
-                            loop {
-                                count += 1;
-                                if count % 200 == 0 {
-                                    match rx.try_recv() {
-                                        //Ok(B) if B => thread::sleep(Duration::from_millis(10)),
-                                        Ok(_) | Err(TryRecvError::Disconnected) => {
-                                            println!("stopping indefinite training thread.");
-                                            break;
+
+                    if let Some(stop) = &self.early_stop {
+                        ui.label(format!("Early stopping: {stop}"));
+                    }
+
+                    if let Some(problem) = &self.numerical_error {
+                        ui.colored_label(Color32::LIGHT_RED, format!(
+                            "Training paused: {problem}. The weights went back to the last check without any; \
+                            lower the learning rate or clip the gradients, then resume."));
+                    }
+
+                    if let Some(failure) = &self.training_failure {
+                        ui.colored_label(Color32::LIGHT_RED, format!("Training stopped: {failure}."));
+                    }
+
+                    ui.add(egui::Slider::new(&mut self.settings.regularization.l1, 0.0..=0.001)
+                        .logarithmic(true)
+                        .text("L1 Penalty")
+                    );
+
+                    ui.add(egui::Slider::new(&mut self.settings.regularization.l2, 0.0..=0.01)
+                        .logarithmic(true)
+                        .text("L2 Penalty (Weight Decay)")
+                    );
+
+                    ui.horizontal_top(|ui| {
+                        let mut clip_value = self.settings.gradient_clipping.value.is_some();
+                        if ui.checkbox(&mut clip_value, "Clip Gradient Values").changed() {
+                            self.settings.gradient_clipping.value = clip_value.then_some(1.0);
+                        }
+                        if let Some(value) = &mut self.settings.gradient_clipping.value {
+                            ui.add(egui::DragValue::new(value).range(0.001..=100.0).speed(0.01).prefix("±"));
+                        }
+
+                        let mut clip_norm = self.settings.gradient_clipping.norm.is_some();
+                        if ui.checkbox(&mut clip_norm, "Clip Gradient Norm").changed() {
+                            self.settings.gradient_clipping.norm = clip_norm.then_some(5.0);
+                        }
+                        if let Some(norm) = &mut self.settings.gradient_clipping.norm {
+                            ui.add(egui::DragValue::new(norm).range(0.001..=1000.0).speed(0.05).prefix("max: "));
+                        }
+                    });
+
+                    ui.horizontal_top(|ui| {
+                        if ui.button("Save Model").clicked() {
+                            self.model_file_status = Some(match self.nn.save(MODEL_PATH) {
+                                Ok(()) => format!("saved to {MODEL_PATH}"),
+                                Err(e) => format!("couldn't save to {MODEL_PATH}: {e}"),
+                            });
+                        }
+
+                        if ui.button("Load Model").clicked() {
+                            self.model_file_status = Some(if self.training.is_some() {
+                                "stop training before loading a model".to_string()
+                            } else {
+                                match NeuralNet::load(MODEL_PATH) {
+                                    Ok(nn) => {
+                                        self.load_settings_from(&nn);
+                                        self.replace_nn(nn);
+                                        format!("loaded {MODEL_PATH} (seed {})", self.seed)
+                                    }
+                                    Err(e) => format!("couldn't load {MODEL_PATH}: {e}"),
+                                }
+                            });
+                        }
+
+                        if let Some(status) = &self.model_file_status {
+                            ui.label(status);
+                        }
+                    });
+
+                    if ui.button("Check Gradients").clicked() {
+                        let data_point = &self.training_data[rand::random_range(0..self.training_data.len())];
+                        self.gradient_check = Some(self.nn.gradient_check(data_point, 0.01, 50));
+                    }
+
+                    if let Some(report) = &self.gradient_check {
+                        ui.label(format!("Gradient check, max relative error: {:.2e}", report.max_relative_error()));
+                        for check in &report.layers {
+                            ui.label(format!("    {}: max {:.2e}, mean {:.2e} over {} parameters",
+                                check.name, check.max_relative_error, check.mean_relative_error, check.checked));
+                        }
+                    }
+
+                },
+                
+                View::Model => {
+                    self.model_builder.ui(ui);
+
+                    ui.horizontal_top(|ui| {
+                        egui::ComboBox::from_label("Hidden Activation")
+                            .selected_text(format!("{:?}", self.settings.hidden_activation))
+                            .show_ui(ui, |ui| {
+                                for activation in Activation::ALL {
+                                    ui.selectable_value(&mut self.settings.hidden_activation, activation, format!("{activation:?}"));
+                                }
+                            });
+
+                        egui::ComboBox::from_label("Output Activation")
+                            .selected_text(format!("{:?}", self.settings.output_activation))
+                            .show_ui(ui, |ui| {
+                                for activation in Activation::ALL {
+                                    ui.selectable_value(&mut self.settings.output_activation, activation, format!("{activation:?}"));
+                                }
+                            });
+
+                        egui::ComboBox::from_label("Loss")
+                            .selected_text(format!("{:?}", self.settings.loss))
+                            .show_ui(ui, |ui| {
+                                for loss in Loss::ALL {
+                                    ui.selectable_value(&mut self.settings.loss, loss, format!("{loss:?}"));
+                                }
+                            });
+                    });
+
+                    ui.add(egui::Slider::new(&mut self.settings.dropout, 0.0..=0.8)
+                        .text("Hidden Layer Dropout")
+                    );
+
+                    // normalizes over each batch, so it needs a batch size above 1 to do anything.
+                    ui.horizontal_top(|ui| {
+                        ui.checkbox(&mut self.settings.batch_norm, "Batch Norm (hidden layers)");
+
+                        if self.settings.batch_norm {
+                            if self.batch_size < 2 {
+                                ui.label("(does nothing with a batch size of 1)");
+                            } else if !self.hogwild && self.batch_size < 2 * self.training_threads {
+                                // each thread normalizes its own part of the batch.
+                                ui.label(format!("(batches this small only get split over {} threads)", self.batch_size / 2));
+                            }
+                        }
+                    });
+
+                    // only used when the weights are (re)drawn.
+                    ui.horizontal_top(|ui| {
+                        egui::ComboBox::from_label("Weight Initialization")
+                            .selected_text(self.settings.initialization.name())
+                            .show_ui(ui, |ui| {
+                                for initialization in Initialization::ALL {
+                                    let selected = self.settings.initialization.name() == initialization.name();
+                                    if ui.selectable_label(selected, initialization.name()).clicked() && !selected {
+                                        self.settings.initialization = initialization;
+                                    }
+                                }
+                            });
+
+                        if let Initialization::Constant(value) = &mut self.settings.initialization {
+                            ui.add(egui::DragValue::new(value).speed(0.001).prefix("value: "));
+                        }
+
+                        ui.add(egui::DragValue::new(&mut self.seed).prefix("Seed: "));
+                        if ui.button("New Seed").clicked() {
+                            self.seed = rand::random();
+                        }
+                    });
+
+                    ui.horizontal_top(|ui| {
+                        // the workers share nothing but the weights, so there's nowhere to keep the running averages.
+                        ui.add_enabled_ui(!self.hogwild, |ui| {
+                            egui::ComboBox::from_label("Optimizer")
+                                .selected_text(self.settings.optimizer.name())
+                                .show_ui(ui, |ui| {
+                                    for optimizer in Optimizer::ALL {
+                                        let selected = self.settings.optimizer.name() == optimizer.name();
+                                        if ui.selectable_label(selected, optimizer.name()).clicked() && !selected {
+                                            self.settings.optimizer = optimizer;
                                         }
-                                        Err(TryRecvError::Empty) => {}
                                     }
+                                });
 
-                                    p_points.write().unwrap().extend_from_slice(&vals[..]);
-                                    vals.clear();
-                                    ctx_arc.request_repaint();
+                            match &mut self.settings.optimizer {
+                                Optimizer::Sgd => {}
+                                Optimizer::Momentum { beta } => {
+                                    ui.add(egui::DragValue::new(beta).range(0.0..=0.999).speed(0.001).prefix("beta: "));
+                                }
+                                Optimizer::Adam { beta1, beta2, .. } => {
+                                    ui.add(egui::DragValue::new(beta1).range(0.0..=0.999).speed(0.001).prefix("beta1: "));
+                                    ui.add(egui::DragValue::new(beta2).range(0.0..=0.9999).speed(0.0001).prefix("beta2: "));
                                 }
-                                
-                                vals.push(nn.write().unwrap().train_one(&training_data[rand::random_range(0..training_data.len())]));
                             }
                         });
-                    }
 
-                    if ui.button("Stop Training").clicked() {
+                        if self.hogwild {
+                            ui.label("(hogwild workers always use SGD)");
+                        }
+                    });
+
+                    // only picked up when training is (re)started.
+                    ui.add(egui::Slider::new(&mut self.batch_size, 1..=256)
+                        .logarithmic(true)
+                        .text("Batch Size")
+                    );
 
-                        if let Some(tx) = self.training_thread_tx.as_ref() {
-                            let _ = tx.send(());
+                    let names: Vec<String> = self.nn.layers().layers().iter().map(|layer| layer.name()).collect();
+                    ui.label(format!("Layers: {}", names.join(", ")));
+
+                    ui.horizontal_top(|ui| {
+                        if ui.button("Build Model").clicked() {
+                            self.model_file_status = Some(if self.training.is_some() {
+                                "stop training before replacing the model".to_string()
+                            } else {
+                                let mut nn = self.model_builder.build();
+                                self.settings.apply(&mut nn);
+                                nn.populate_seeded_weights(self.seed);
+                                self.replace_nn(nn);
+                                format!("built a fresh model ({:?}, seed {})", self.model_builder.structure(), self.seed)
+                            });
                         }
 
-                        self.training_thread_tx = None;
-                    }
+                        for (label, preset) in [("New Dense Net", NeuralNet::new as fn() -> _), ("New LeNet", NeuralNet::lenet)] {
+                            if ui.button(label).clicked() {
+                                self.model_file_status = Some(if self.training.is_some() {
+                                    "stop training before replacing the model".to_string()
+                                } else {
+                                    let mut nn = preset();
+                                    // the presets come with their own initialization.
+                                    nn.populate_seeded_weights(self.seed);
+                                    self.load_settings_from(&nn);
+                                    self.replace_nn(nn);
+                                    format!("started a fresh model ({label}, seed {})", self.seed)
+                                });
+                            }
+                        }
 
-                    ui.add(egui::Slider::new(&mut self.learning_rate, 0.0001..=0.4)
-                        .clamping(egui::SliderClamping::Edits)
-                        .text("Learning Rate")
-                    );
+                        if ui.button("Reinitialize Weights").clicked() {
+                            self.model_file_status = Some(if self.training.is_some() {
+                                "stop training before reinitializing the weights".to_string()
+                            } else {
+                                self.nn.populate_seeded_weights(self.seed);
+                                self.nn_version += 1;
+                                format!("reinitialized the weights ({}, seed {})", self.settings.initialization.name(), self.seed)
+                            });
+                        }
 
-                    if let Ok(mut nn) = self.nn.try_write() {
-                        nn.set_learning_rate(self.learning_rate);
+                        if let Some(status) = &self.model_file_status {
+                            ui.label(status);
+                        }
+                    });
+
+                    if self.nn.structure().is_some_and(|structure| structure != self.model_builder.structure()) {
+                        ui.label("The layers above haven't been built yet.");
                     }
+                },
 
+                View::Weights => self.weights_view.show(ui, &self.nn),
+
+                View::Attack => {
+                    let training = self.training_state() == TrainingState::Running;
+                    self.attack_view.show(ui, &self.nn, self.nn_version, training, &self.testing_data);
+                }
 
-                },
-                
                 View::InspectData => {
                     
 
@@ -286,10 +764,13 @@ impl eframe::App for MyApp {
                             self.data_view_texture = Some(texture);
                         }
 
-                        ui.add(widgets::Image::from_texture(
+                        let response = ui.add(widgets::Image::from_texture(
                                 &self.data_view_texture.clone().unwrap())
                                 .maintain_aspect_ratio(true).fit_to_fraction([0.8,0.8].into()));
 
+                        let input = neural_net::scale_and_normalize_data(&self.training_data[self.data_view_index].data);
+                        self.saliency.paint(ui, response.rect, &self.nn, self.nn_version, &input);
+
                         ui.label(egui::widget_text::RichText::new(
                                 format!("{}", self.training_data[self.data_view_index].label))
                             .size(20.0));
@@ -300,18 +781,16 @@ impl eframe::App for MyApp {
                         );
 
                         ui.horizontal_top(|ui| {
-                            if ui.button("<").clicked() {
-                                if self.data_view_index > 0 {
-                                    self.data_view_index -= 1;
-                                }
+                            if ui.button("<").clicked() && self.data_view_index > 0 {
+                                self.data_view_index -= 1;
                             }
 
-                            if ui.button(">").clicked() {
-                                if self.data_view_index < self.training_data.len() - 1 {
-                                    self.data_view_index += 1;
-                                }
+                            if ui.button(">").clicked() && self.data_view_index < self.training_data.len() - 1 {
+                                self.data_view_index += 1;
                             }
                         });
+
+                        self.saliency.controls(ui);
                     });
 
                 },
@@ -330,6 +809,8 @@ impl eframe::App for MyApp {
                                 &self.drawing_texture.clone().unwrap()
                         ).maintain_aspect_ratio(true).fit_to_fraction([0.8,0.8].into()));
 
+                        self.saliency.paint(ui, response.rect, &self.nn, self.nn_version, &self.drawing_input());
+
                         if response.contains_pointer() {
                             if ui.input(|i| i.pointer.primary_down()) {
                                 if let Some(mouse_pos) = ctx.pointer_latest_pos() {
@@ -351,28 +832,7 @@ impl eframe::App for MyApp {
                                             }
                                         }   
                                     }
-                                    self.prev_brush_pos = Some(uv.clone());
-
-                                    if let Ok(nn) = self.nn.try_read() {
-                                        let prediction = nn.image_to_prediction(
-                                            neural_net::scale_and_normalize_data(
-                                                &canvas.get_pixels_as_slice()
-                                                .iter()
-                                                .map(|color|
-                                                    (255.0 - (
-                                                        color.r() as f32 +
-                                                        color.g() as f32 +
-                                                        color.b() as f32
-                                                    ) / (3.0)) as u8
-                                                ).collect::<Vec<u8>>()
-                                            )
-                                        );
-
-                                        for i in 0..10 {
-                                            self.outputs[i] = prediction[i];
-                                        }
-                                    }
-
+                                    self.prev_brush_pos = Some(uv);
                                 }
                             } else {
                                 // mouse is not clicked; break brush line.
@@ -383,16 +843,13 @@ impl eframe::App for MyApp {
                             self.prev_brush_pos = None;
                         }
 
-                        let mut max = -1.0;
-                        let mut final_prediction = 0;
-                        for i in 0..10 {
-                            if self.outputs[i] > max {
-                                max = self.outputs[i];
-                                final_prediction = i;
-                            }
-                        }
+                        // predicted every frame, so it keeps up with the weights while training.
+                        let prediction = self.nn.image_to_prediction(self.drawing_input());
+                        self.outputs.copy_from_slice(&prediction[..10]);
+
+                        ui.label(neural_net::argmax(&self.outputs).to_string());
 
-                        ui.label(final_prediction.to_string());
+                        self.saliency.controls(ui);
 
                         let canvas = &mut self.drawing_data.write().unwrap();
 
@@ -0,0 +1,81 @@
+use eframe::egui;
+
+use crate::neural_net::NeuralNet;
+
+// an MNIST image in, a score for every digit out.
+const INPUTS: usize = 28 * 28;
+const OUTPUTS: usize = 10;
+
+/// The dense layers of a net for the Model view to build; a draft until it's built, so
+/// editing it never touches the net being trained.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelBuilder {
+    /// Size of every hidden layer, from the input's side.
+    pub hidden_sizes: Vec<usize>,
+}
+
+impl Default for ModelBuilder {
+    fn default() -> Self {
+        Self {
+            hidden_sizes: vec![160],
+        }
+    }
+}
+
+impl ModelBuilder {
+    pub fn structure(&self) -> Vec<usize> {
+        let mut structure = vec![INPUTS];
+        structure.extend(&self.hidden_sizes);
+        structure.push(OUTPUTS);
+        structure
+    }
+
+    /// A fresh net with these layers, before any settings or weights.
+    pub fn build(&self) -> NeuralNet {
+        NeuralNet::with_structure(self.structure())
+    }
+
+    /// Take on the layers of `nn`; a net with other kinds of layers (like LeNet) leaves the
+    /// draft as it was.
+    pub fn read_from(&mut self, nn: &NeuralNet) {
+        if let Some(structure) = nn.structure() {
+            self.hidden_sizes = structure[1..structure.len() - 1].to_vec();
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(format!("Input: {INPUTS}"));
+
+        let mut insert = None;
+        let mut remove = None;
+
+        for (i, size) in self.hidden_sizes.iter_mut().enumerate() {
+            ui.horizontal_top(|ui| {
+                ui.label(format!("Hidden layer {}:", i + 1));
+                ui.add(egui::DragValue::new(size).range(1..=4096).suffix(" units"));
+
+                if ui.button("Add Below").clicked() {
+                    insert = Some(i + 1);
+                }
+                if ui.button("Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+
+        // new layers start out the size of the one above them.
+        if let Some(i) = insert {
+            self.hidden_sizes.insert(i, self.hidden_sizes[i - 1]);
+        }
+        if let Some(i) = remove {
+            self.hidden_sizes.remove(i);
+        }
+
+        ui.horizontal_top(|ui| {
+            ui.label(format!("Output: {OUTPUTS}"));
+            if self.hidden_sizes.is_empty() && ui.button("Add Hidden Layer").clicked() {
+                self.hidden_sizes.push(160);
+            }
+        });
+    }
+}
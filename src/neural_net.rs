@@ -1,19 +1,78 @@
 
 mod math;
+pub mod layer;
+pub mod init;
+pub mod sequential;
+pub mod dense;
+pub mod activation;
+pub mod dropout;
+pub mod batch_norm;
+pub mod conv;
+pub mod pooling;
+pub mod loss;
+pub mod regularization;
+pub mod clipping;
+pub mod optimizer;
+pub mod autograd;
+pub mod gradient_check;
+pub mod saliency;
+pub mod adversarial;
+pub mod validation;
+pub mod health;
+pub mod history;
+pub mod parallel;
+pub mod hogwild;
+pub mod model_file;
+
+#[cfg(test)]
+pub(crate) mod tests;
 
 use math::*;
-use rand::rng;
-use rand_distr::{Normal, Distribution};
+use layer::{ Layer, LayerGradients, running_average };
+use sequential::{ Sequential, ForwardCache };
+use autograd::{ Tape, Var };
+use init::Initialization;
+use dense::Dense;
+use activation::Activation;
+use dropout::Dropout;
+use batch_norm::BatchNorm;
+use conv::{ Conv2D, Shape };
+use pooling::MaxPool2D;
+use loss::Loss;
+use regularization::Regularization;
+use clipping::GradientClipping;
+use optimizer::{ Optimizer, OptimizerState };
+use rand::{rng, Rng, RngCore, SeedableRng};
+use rand::rngs::StdRng;
+
+use std::iter::zip;
+use std::ops::{ AddAssign, Range };
 
 pub struct NNData {
     pub data: Vec<u8>,
     pub label: usize,
 }
 
-#[derive(Debug)]
+/// Whether the network is being trained (dropout active) or used for predictions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Training,
+    Inference,
+}
+
+#[derive(Debug, Clone)]
 pub struct NeuralNet {
-    weights: Vec<Matrix>,
+    layers: Sequential,
+    loss: Loss,
+    regularization: Regularization,
+    gradient_clipping: GradientClipping,
+    optimizer: Optimizer,
+    optimizer_state: OptimizerState,
     learning_rate: f32,
+    initialization: Initialization,
+    mode: Mode,
+    seed: u64,
+    rng: StdRng, // for dropout in `train_one`, and the trainers' seeds
 }
 
 impl NeuralNet {
@@ -28,69 +87,315 @@ impl NeuralNet {
         // the structure of the network for the MNIST dataset; input layer
         // can take in an image, middle layer for processing, output layer has
         // one node per possible label.
-        let net_structure = vec![28 * 28, 160, 10];
+        Self::with_structure(vec![28 * 28, 160, 10])
+    }
+
+    /// Build a network of dense sigmoid layers with an arbitrary structure; see `new` for
+    /// the meaning of `net_structure`.
+    pub fn with_structure(net_structure: impl Vector<usize>) -> Self {
+        let sizes: Vec<usize> = net_structure.elements().collect();
+
+        // need at least input and output.
+        assert!(sizes.len() >= 2);
+
+        let mut layers = Sequential::new();
+
+        for size in sizes.windows(2) {
+            layers.push(Dense::new(size[0], size[1]));
+            layers.push(Activation::Sigmoid);
+        }
+
+        Self::from_layers(layers)
+    }
+
+    /// A small LeNet-5 style convolutional network for MNIST: two convolution + max pooling
+    /// stages followed by three dense layers, trained with softmax and cross-entropy.
+    pub fn lenet() -> Self {
+        let mut layers = Sequential::new();
+
+        let conv1 = Conv2D::new(Shape::new(1, 28, 28), 6, 5, 1, 2);
+        let pool1 = MaxPool2D::new(conv1.output_shape(), 2, 2);
+        let conv2 = Conv2D::new(pool1.output_shape(), 16, 5, 1, 0);
+        let pool2 = MaxPool2D::new(conv2.output_shape(), 2, 2);
+        let features = pool2.output_shape().size();
+
+        layers.push(conv1);
+        layers.push(Activation::Relu);
+        layers.push(pool1);
+        layers.push(conv2);
+        layers.push(Activation::Relu);
+        layers.push(pool2);
+        layers.push(Dense::new(features, 120));
+        layers.push(Activation::Relu);
+        layers.push(Dense::new(120, 84));
+        layers.push(Activation::Relu);
+        layers.push(Dense::new(84, 10));
+        layers.push(Activation::Softmax);
+
+        let mut nn = Self::from_layers(layers);
+        nn.loss = Loss::CrossEntropy;
+        nn.learning_rate = 0.02;
+        nn.initialization = Initialization::HeNormal;
+        nn
+    }
+
+    /// Build a network from any stack of layers, with the default loss and settings.
+    pub fn from_layers(layers: Sequential) -> Self {
+        let seed = rng().random();
+
+        NeuralNet {
+            layers,
+            loss: Loss::SquaredError,
+            regularization: Regularization::default(),
+            gradient_clipping: GradientClipping::default(),
+            optimizer: Optimizer::Sgd,
+            optimizer_state: OptimizerState::default(),
+            learning_rate: 0.06,
+            initialization: Initialization::LeCunNormal,
+            mode: Mode::Training,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn layers(&self) -> &Sequential {
+        &self.layers
+    }
+
+    /// The first layer, if it's dense; each of its units weighs the input values directly.
+    pub fn first_layer(&self) -> Option<&Dense> {
+        self.layers.layers().first()?.as_any().downcast_ref::<Dense>()
+    }
+
+    /// The `net_structure` that `with_structure` would build this network from, if it's only
+    /// dense layers (with their activations, dropout and batch norm).
+    pub fn structure(&self) -> Option<Vec<usize>> {
+        let layers = self.layers.layers();
+        let dense: Vec<&Dense> = layers.iter().filter_map(|layer| layer.as_any().downcast_ref::<Dense>()).collect();
+
+        let only_dense = layers.iter().all(|layer| {
+            let layer = layer.as_any();
+            layer.is::<Dense>() || layer.is::<Activation>() || layer.is::<Dropout>() || layer.is::<BatchNorm>()
+        });
+        if !only_dense || dense.is_empty() {
+            return None;
+        }
 
-        let mut weights: Vec<Matrix> = Vec::new();
+        let mut structure = vec![dense[0].inputs()];
+        structure.extend(dense.iter().map(|layer| layer.outputs()));
+        Some(structure)
+    }
 
-        let num_layers = net_structure.size();
+    /// The number of dense layers.
+    ///
+    /// The per-layer settings (`set_activation`, `set_dropout` and `set_batch_norm`) refer to
+    /// a dense layer together with the layers after it, up to the next dense layer; layer `0`
+    /// is the first dense layer.
+    pub fn num_dense_layers(&self) -> usize {
+        self.dense_layer_starts().len()
+    }
 
-        weights.reserve_exact(num_layers);
+    fn dense_layer_starts(&self) -> Vec<usize> {
+        self.layers.layers().iter()
+            .enumerate()
+            .filter(|(_, layer)| layer.as_any().is::<Dense>())
+            .map(|(i, _)| i)
+            .collect()
+    }
 
-        let mut net_structure_iter = net_structure.elements();
+    // the dense layer and the layers belonging to it, as indices into `self.layers`.
+    fn dense_block(&self, layer: usize) -> Range<usize> {
+        let starts = self.dense_layer_starts();
+        starts[layer]..starts.get(layer + 1).copied().unwrap_or(self.layers.layers().len())
+    }
 
-        // need at least input and output, so this should work.
-        let mut n = net_structure_iter.next().unwrap();
-        let mut m = net_structure_iter.next().unwrap();
+    // where the first `T` in a dense layer's block is, if it has one.
+    fn find_in_block<T: Layer + 'static>(&self, layer: usize) -> Option<usize> {
+        self.dense_block(layer).find(|&i| self.layers.layers()[i].as_any().is::<T>())
+    }
 
-        loop {
-            weights.push(Matrix::new(m, n));
-            
-            n = m;
+    fn block_layer<T: Layer + 'static>(&self, layer: usize) -> Option<&T> {
+        let i = self.find_in_block::<T>(layer)?;
+        self.layers.layers()[i].as_any().downcast_ref::<T>()
+    }
 
-            if let Some(next) = net_structure_iter.next() {
-                m = next;
-            } else {
-                break;
+    /// Set the activation function after a dense layer (`0` is the first layer after the input).
+    pub fn set_activation(&mut self, layer: usize, activation: Activation) {
+        match self.find_in_block::<Activation>(layer) {
+            Some(i) => *self.layers.layers_mut()[i].as_any_mut().downcast_mut::<Activation>().unwrap() = activation,
+            None => {
+                // after the dense layer, and its batch norm if it has one.
+                let i = self.find_in_block::<BatchNorm>(layer).unwrap_or(self.dense_block(layer).start);
+                self.layers.insert(i + 1, activation);
             }
         }
+    }
 
-        //println!("{:?}", weights);
+    pub fn activation(&self, layer: usize) -> Option<Activation> {
+        self.block_layer::<Activation>(layer).copied()
+    }
 
+    /// Set the probability of each output of a layer being dropped (zeroed) while training.
+    /// Kept outputs are scaled up by `1 / (1 - probability)` so inference needs no rescaling.
+    pub fn set_dropout(&mut self, layer: usize, probability: F) {
+        assert!((0.0..1.0).contains(&probability));
 
-        NeuralNet {
-            weights,
-            learning_rate: 0.06,
+        match self.find_in_block::<Dropout>(layer) {
+            Some(i) if probability == 0.0 => {
+                self.layers.remove(i);
+            }
+            Some(i) => *self.layers.layers_mut()[i].as_any_mut().downcast_mut::<Dropout>().unwrap() = Dropout::new(probability),
+            None if probability > 0.0 => self.layers.insert(self.dense_block(layer).end, Dropout::new(probability)),
+            None => {}
+        }
+    }
+
+    pub fn dropout(&self, layer: usize) -> F {
+        self.block_layer::<Dropout>(layer).map_or(0.0, |dropout| dropout.probability())
+    }
+
+    /// Add (or remove) batch normalization of a layer's pre-activations, between its
+    /// weights and its activation function. Adding it to layer `l` normalizes what's
+    /// passed from the dense layer `l` to the dense layer `l + 1`.
+    ///
+    /// While training, the statistics come from each batch, so it only has an effect
+    /// with batches of more than one sample.
+    pub fn set_batch_norm(&mut self, layer: usize, enabled: bool) {
+        match self.find_in_block::<BatchNorm>(layer) {
+            Some(i) if !enabled => {
+                self.layers.remove(i);
+            }
+            None if enabled => {
+                let outputs = self.block_layer::<Dense>(layer).unwrap().outputs();
+                self.layers.insert(self.dense_block(layer).start + 1, BatchNorm::new(outputs));
+            }
+            _ => {}
         }
     }
 
+    pub fn has_batch_norm(&self, layer: usize) -> bool {
+        self.find_in_block::<BatchNorm>(layer).is_some()
+    }
+
+    /// Dropout only happens in `Mode::Training`; predictions are always made as in
+    /// `Mode::Inference`, so they stay deterministic.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    pub fn loss_function(&self) -> Loss {
+        self.loss
+    }
+
+    pub fn set_loss(&mut self, loss: Loss) {
+        self.loss = loss;
+    }
+
+    pub fn regularization(&self) -> Regularization {
+        self.regularization
+    }
+
+    pub fn set_regularization(&mut self, regularization: Regularization) {
+        self.regularization = regularization;
+    }
+
+    pub fn gradient_clipping(&self) -> GradientClipping {
+        self.gradient_clipping
+    }
+
+    pub fn set_gradient_clipping(&mut self, clipping: GradientClipping) {
+        self.gradient_clipping = clipping;
+    }
+
+    pub fn optimizer(&self) -> Optimizer {
+        self.optimizer
+    }
+
+    /// Changing the optimizer (or its settings) starts its running averages over.
+    pub fn set_optimizer(&mut self, optimizer: Optimizer) {
+        if optimizer != self.optimizer {
+            self.optimizer = optimizer;
+            self.optimizer_state.reset();
+        }
+    }
+
+    pub fn learning_rate(&self) -> f32 {
+        self.learning_rate
+    }
+
     pub fn set_learning_rate(&mut self, rate: f32) {
         self.learning_rate = rate;
     }
 
-    pub fn populate_random_weights(&mut self) {
-        let mut rng = rng();
-        for matrix in &mut self.weights {
-            let bound = (matrix.m() as f32).sqrt();
-            let normal = Normal::new(0.0, 1.0/bound).unwrap();
-            matrix.apply_fn(|x| *x = normal.sample(&mut rng));
-        }
+    pub fn initialization(&self) -> Initialization {
+        self.initialization
+    }
+
+    /// How `populate_seeded_weights` draws the weights.
+    pub fn set_initialization(&mut self, initialization: Initialization) {
+        self.initialization = initialization;
+    }
+
+    /// Start a reproducible run: the weights, dropout masks in `train_one` and every seed
+    /// from `next_seed` all come from one rng seeded with `seed`. So with the same settings
+    /// and data, the same seed gives bit-identical weights after the same training steps
+    /// (except with hogwild, whose threads race by design).
+    pub fn populate_seeded_weights(&mut self, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        self.layers.initialize(self.initialization, &mut rng);
+        self.optimizer_state.reset();
+
+        self.seed = seed;
+        self.rng = rng;
+    }
+
+    /// The seed of the current run; see `populate_seeded_weights`.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// A seed for a trainer's rng (batch sampling and dropout), drawn from this run's seed.
+    pub fn next_seed(&mut self) -> u64 {
+        self.rng.random()
+    }
+
+    // every trainable value, layer by layer. `Gradients::parameters` has the same layout.
+    fn parameters(&self) -> Vec<&[F]> {
+        self.layers.parameters()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut [F]> {
+        self.layers.parameters_mut()
+    }
+
+    // values tracked from the training batches, like batch norm's running mean and
+    // variance; `Gradients::batch_statistics` has the same layout.
+    fn statistics(&self) -> Vec<&[F]> {
+        self.layers.statistics()
+    }
+
+    fn statistics_mut(&mut self) -> Vec<&mut [F]> {
+        self.layers.statistics_mut()
     }
 
     // feeds value through neural network, returns output at each layer.
     pub fn nn_process_forward<V>(&self, input: V) -> Vec<Vec<F>>
     where V: Vector<F> {
-        //println!("feeding a value into the network: {:?}",input);
-        let mut values: Vec<Vec<F>> = Vec::new();
-        values.reserve_exact(self.weights.len() + 1);
-
-        values.push(input.elements().collect());
+        self.forward_cache(input).activations().map(|a| a.get_row(0)).collect()
+    }
 
-        for layer in &self.weights {
-            //println!("\n\nThis layer has {} nodes.", layer.n());
-            values.push((layer * values.last().unwrap()).iter().map(|&x| sigmoid(x)).collect());
-        }
+    /// Feeds a value through the network as in inference mode, keeping everything
+    /// backpropagation needs.
+    pub fn forward_cache<V>(&self, input: V) -> ForwardCache
+    where V: Vector<F> {
+        let size = input.size();
+        self.forward(Matrix::from_values(input, 1, size), None)
+    }
 
-        values
+    // feeds a batch (one sample per row) through the network. Layers only behave as in
+    // training when there's an rng (to draw dropout masks from) and we're training.
+    fn forward(&self, inputs: Matrix, rng: Option<&mut dyn RngCore>) -> ForwardCache {
+        self.layers.forward(inputs, rng.filter(|_| self.mode == Mode::Training))
     }
 
     pub fn image_to_prediction<V>(&self, input: V) -> Vec<F> 
@@ -98,69 +403,150 @@ impl NeuralNet {
         self.nn_process_forward(input).last().unwrap().clone()
     }
 
+    /// The value of the loss function for one data point.
+    pub fn loss(&self, data_point: &NNData) -> F {
+        let mut cache = self.forward(batch_inputs(&[data_point]), None);
+        let loss = cache.record(|tape, output| self.record_loss(tape, output, batch_targets(&[data_point])));
+        cache.value(loss).get_raw_slice()[0]
+    }
+
+    // records the loss of every sample (one per row of `output`) on `tape`, as a column.
+    fn record_loss(&self, tape: &mut Tape, output: Var, targets: Matrix) -> Var {
+        let targets = tape.leaf(targets);
+        let losses = self.loss.record(tape, output, targets);
+        tape.sum_rows(losses)
+    }
+
     // For stochastic gradient descent, uses one data point at a time.
-    // Returns summed error.
+    // Returns the loss of the data point, like `loss` (without the regularization penalty).
     pub fn train_one(&mut self, data_point: &NNData) -> f32 {
-        // convert data to float inputs, shifting and scaling slightly:
-        let input_data: Vec<f32> = scale_and_normalize_data(&data_point.data);
-
-        // create target value:
-        let mut target: Vec<f32> = vec![0.01;10];
-        target[data_point.label] = 0.99;
-        let target = target; // no longer mutable!
+        let mut gradients = self.zero_gradients();
 
+        // `accumulate_gradients` borrows all of self, so use a copy of the rng:
+        let mut rng = self.rng.clone();
+        let loss = self.accumulate_gradients(&[data_point], &mut gradients, &mut rng)[0];
+        self.rng = rng;
 
-        // feed data through network:
-        let neuron_values = self.nn_process_forward(input_data);
-        let output = &neuron_values.last().unwrap();
+        self.apply_gradients(&gradients);
 
-        //println!("\n\ntarget: {:?}\nactual: {:?}", target, output);
+        loss
+    }
 
-        // get output error:
-        let mut error = vec![0.0;10];
-        for i in 0..10 {
-            error[i] = output[i] - target[i];
-        }
+    /// Get a set of gradients shaped like this network's parameters, all values initialized to 0.
+    pub fn zero_gradients(&self) -> Gradients {
+        self.layers.zero_gradients()
+    }
 
-        let scalar_error: f32 = error.iter().fold(0.0, |sum, x| sum + x.abs());
+    /// Computes the gradient of the summed loss over a batch and adds it onto `gradients`,
+    /// without touching the weights. Returns the loss of each data point.
+    ///
+    /// Regularization isn't included, in the gradients or the losses; it only depends on the
+    /// weights, so `apply_gradients` adds it once per step instead of once per sample.
+    ///
+    /// Dropout masks (in training mode) are drawn from `rng`, and batch norm layers use the
+    /// statistics of this batch.
+    pub fn accumulate_gradients(&self, batch: &[&NNData], gradients: &mut Gradients, rng: &mut impl Rng) -> Vec<F> {
+        let mut cache = self.forward(batch_inputs(batch), Some(rng as &mut dyn RngCore));
+        let targets = batch_targets(batch);
 
-        // iterate through layers, backpropagating the error and then
-        // adjusting weights according to the gradient and learning rate:
-        for count in 0..self.weights.len() { 
-            let layer = self.weights.len() - 1 - count; // iterate in reverse order.
+        let losses = cache.record(|tape, output| self.record_loss(tape, output, targets));
+        let loss = cache.record(|tape, _| tape.sum(losses));
+        self.layers.backward(&cache, loss, gradients);
 
-            let layer_weight_matrix = &mut (self.weights[layer]); // get layer matrix.
+        cache.value(losses).get_raw_slice().to_vec()
+    }
 
+    /// Take one step of the optimizer using (already averaged and then clipped) gradients,
+    /// plus the gradient of the regularization penalty. Running statistics (batch norm's)
+    /// are moved towards the statistics of the batches the gradients came from.
+    pub fn apply_gradients(&mut self, gradients: &Gradients) {
+        let regularization = self.regularization;
+        let clipping = self.gradient_clipping;
+        let gradient = gradients.parameters();
+        let scale = clipping.norm_scale(gradient.iter().copied());
+
+        self.optimizer.step(&mut self.optimizer_state, self.learning_rate, self.layers.parameters_mut(), |slice, index, w| {
+            scale * clipping.clamp(gradient[slice][index]) + regularization.gradient(w)
+        });
+
+        if let Some(batch_statistics) = gradients.batch_statistics() {
+            for (running, batch) in zip(self.statistics_mut(), batch_statistics) {
+                for (running, batch) in zip(running, batch) {
+                    *running = running_average(*running, batch);
+                }
+            }
+        }
+    }
+}
 
-            //println!("layer weight matrix dims: m = {}, n = {}",layer_weight_matrix.m(),layer_weight_matrix.n());
-            //println!("neuron layers: {}", neuron_values.len());
-            //println!("error length: {}", error.len());
-            // compute gradient, update terms.
-            for i in 0..layer_weight_matrix.m() {
+/// Gradient of the error with respect to every parameter in a `NeuralNet`, one
+/// `LayerGradients` per layer.
+#[derive(Debug, Clone)]
+pub struct Gradients {
+    layers: Vec<LayerGradients>,
+}
 
-                let row = layer_weight_matrix.get_mut_row_slice(i);
+impl Gradients {
+    /// Reset every gradient to 0 so the allocation can be reused.
+    pub fn clear(&mut self) {
+        for layer in &mut self.layers {
+            layer.clear();
+        }
+    }
 
-                let sigmoid_value = sigmoid(dot(row, &neuron_values[layer]).unwrap());
-                                                                                // no -1 because neuron_values
-                                                                                // includes input layer; we
-                                                                                // are using the previous
-                                                                                // layer's neuron values for
-                                                                                // this.
+    pub fn scale(&mut self, scalar: F) {
+        for layer in &mut self.layers {
+            layer.scale(scalar);
+        }
+    }
 
-                // adjust weight based on gradient.
-                for j in 0..row.len() {
-                    row[j] += - self.learning_rate * 2.0 * error[i] * sigmoid_value * (1.0 - sigmoid_value) * neuron_values[layer][j]
-                }
-            }
+    // same layout as `NeuralNet::parameters`.
+    fn parameters(&self) -> Vec<&[F]> {
+        self.layers.iter()
+            .flat_map(|layer| layer.parameters.iter().map(|p| p.as_slice()))
+            .collect()
+    }
 
-            error = &layer_weight_matrix.to_transpose() * &error; // backpropagation baby!
+    // the averaged batch statistics, in the same layout as `NeuralNet::statistics`;
+    // `None` if the layers only used their running statistics.
+    fn batch_statistics(&self) -> Option<Vec<Vec<F>>> {
+        let mut statistics = Vec::new();
+        for layer in self.layers.iter().filter(|layer| !layer.statistics.is_empty()) {
+            statistics.extend(layer.batch_statistics()?);
         }
+        Some(statistics)
+    }
+}
 
-        scalar_error
-
+impl AddAssign<&Gradients> for Gradients {
+    fn add_assign(&mut self, rhs: &Gradients) {
+        for (lhs, rhs) in zip(&mut self.layers, &rhs.layers) {
+            *lhs += rhs;
+        }
     }
 }
 
-pub fn scale_and_normalize_data (data: &Vec<u8>) -> Vec<f32> {
+pub fn scale_and_normalize_data (data: &[u8]) -> Vec<f32> {
     data.iter().map(|x| *x as f32 / 255.0 * 0.98 + 0.01).collect()
 }
+
+/// The index of the largest output, which is the label the net predicts.
+pub fn argmax(output: &[F]) -> usize {
+    (0..output.len()).max_by(|&a, &b| output[a].total_cmp(&output[b])).unwrap()
+}
+
+// the output we want for a label; kept away from 0 and 1, which sigmoid never reaches.
+fn target_values(label: usize) -> Vec<F> {
+    let mut target: Vec<F> = vec![0.01;10];
+    target[label] = 0.99;
+    target
+}
+
+// one sample per row.
+fn batch_inputs(batch: &[&NNData]) -> Matrix {
+    Matrix::from_rows(batch.iter().map(|data_point| scale_and_normalize_data(&data_point.data)).collect())
+}
+
+fn batch_targets(batch: &[&NNData]) -> Matrix {
+    Matrix::from_rows(batch.iter().map(|data_point| target_values(data_point.label)).collect())
+}
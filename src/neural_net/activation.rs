@@ -0,0 +1,79 @@
+
+use super::math::{ F, sigmoid, sigmoid_derivative };
+use super::layer::Layer;
+use super::autograd::{ Tape, Var };
+use super::model_file::{ self, read_u8 };
+
+use rand::RngCore;
+
+use std::io::{ self, Read, Write };
+use std::iter::zip;
+
+/// Function applied to a layer's pre-activations (`W·a`) to get its output; as a `Layer`,
+/// it's applied to each sample separately.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Activation {
+    Sigmoid,
+    Tanh,
+    Relu,
+    /// Normalises the whole layer into a probability distribution; meant for the output layer.
+    Softmax,
+}
+
+impl Activation {
+    pub const ALL: [Activation; 4] = [
+        Activation::Sigmoid,
+        Activation::Tanh,
+        Activation::Relu,
+        Activation::Softmax,
+    ];
+
+    pub fn apply(&self, z: &[F]) -> Vec<F> {
+        match self {
+            Activation::Sigmoid => z.iter().map(|&x| sigmoid(x)).collect(),
+            Activation::Tanh => z.iter().map(|x| x.tanh()).collect(),
+            Activation::Relu => z.iter().map(|x| x.max(0.0)).collect(),
+            Activation::Softmax => {
+                // shifting by the max doesn't change the result but keeps exp() finite.
+                let max = z.iter().fold(F::NEG_INFINITY, |max, &x| max.max(x));
+                let exps: Vec<F> = z.iter().map(|x| (x - max).exp()).collect();
+                let sum: F = exps.iter().sum();
+                exps.iter().map(|x| x / sum).collect()
+            }
+        }
+    }
+
+    /// Given the gradient of the loss with respect to this layer's outputs `f(z)`,
+    /// gives the gradient with respect to the pre-activations `z`.
+    pub fn backpropagate(&self, z: &[F], grad: &[F]) -> Vec<F> {
+        match self {
+            Activation::Sigmoid => zip(z, grad).map(|(&z, g)| g * sigmoid_derivative(z)).collect(),
+            Activation::Tanh => zip(z, grad).map(|(z, g)| g * (1.0 - z.tanh().powi(2))).collect(),
+            Activation::Relu => zip(z, grad).map(|(&z, &g)| if z > 0.0 { g } else { 0.0 }).collect(),
+            Activation::Softmax => {
+                // every output depends on every input: dz_i = a_i (g_i - Σ_j g_j a_j).
+                let a = self.apply(z);
+                let weighted: F = zip(&a, grad).map(|(a, g)| a * g).sum();
+                zip(&a, grad).map(|(a, g)| a * (g - weighted)).collect()
+            }
+        }
+    }
+
+    pub(super) fn read_from(reader: &mut dyn Read) -> io::Result<Self> {
+        model_file::from_index(&Activation::ALL, read_u8(reader)?).copied()
+    }
+}
+
+impl Layer for Activation {
+    fn name(&self) -> String {
+        format!("{self:?}")
+    }
+
+    fn forward(&self, tape: &mut Tape, input: Var, _rng: Option<&mut dyn RngCore>) -> Var {
+        tape.activation(input, *self)
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&[model_file::ACTIVATION, model_file::index_of(&Activation::ALL, *self)])
+    }
+}
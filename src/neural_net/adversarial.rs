@@ -0,0 +1,140 @@
+use super::{ NeuralNet, NNData, argmax, batch_inputs, batch_targets };
+use super::math::{ F, Matrix };
+
+use std::fmt;
+use std::iter::zip;
+
+const ATTACK_BATCH_SIZE: usize = 256;
+
+// the range `scale_and_normalize_data` puts pixels in; attacked images stay images.
+const MIN_VALUE: F = 0.01;
+const MAX_VALUE: F = 0.99;
+
+/// A way to change an input so the net gets its label wrong, moving no value by more than
+/// `epsilon` (so staying in the L∞ ε-ball around it). Values are in the net's input scale,
+/// where black to white is 0.98.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Attack {
+    /// Goodfellow et al.'s fast gradient sign method: one step of `epsilon` along the sign
+    /// of the gradient of the loss.
+    Fgsm { epsilon: F },
+    /// Madry et al.'s projected gradient descent, starting from the input itself: `steps`
+    /// steps of `step_size` along the sign of the gradient, each projected back into the
+    /// ε-ball.
+    Pgd { epsilon: F, step_size: F, steps: usize },
+}
+
+/// How an attack did over a dataset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AttackReport {
+    pub attack: Attack,
+    pub samples: usize,
+    /// Samples predicted correctly before the attack.
+    pub correct: usize,
+    /// Samples predicted correctly before the attack, but not after.
+    pub fooled: usize,
+}
+
+impl Attack {
+    pub const ALL: [Attack; 2] = [
+        Attack::Fgsm { epsilon: 0.1 },
+        Attack::Pgd { epsilon: 0.1, step_size: 0.01, steps: 20 },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Attack::Fgsm { .. } => "FGSM",
+            Attack::Pgd { .. } => "PGD",
+        }
+    }
+
+    pub fn epsilon(&self) -> F {
+        match *self {
+            Attack::Fgsm { epsilon } | Attack::Pgd { epsilon, .. } => epsilon,
+        }
+    }
+}
+
+impl AttackReport {
+    /// The share of correctly predicted samples the attack fooled.
+    pub fn success_rate(&self) -> F {
+        if self.correct == 0 { 0.0 } else { self.fooled as F / self.correct as F }
+    }
+}
+
+impl fmt::Display for AttackReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} with ε {} fooled the net on {} of the {} samples (of {}) it got right: {:.2}% success",
+            self.attack.name(), self.attack.epsilon(), self.fooled, self.correct, self.samples, 100.0 * self.success_rate())
+    }
+}
+
+impl NeuralNet {
+    /// The attacked version of one data point's input.
+    pub fn attack(&self, data_point: &NNData, attack: Attack) -> Vec<F> {
+        self.attack_batch(&[data_point], attack).get_row(0)
+    }
+
+    /// Attacks every data point, as predicted (without dropout or batch statistics).
+    pub fn attack_success_rate(&self, data: &[NNData], attack: Attack) -> AttackReport {
+        let mut correct = 0;
+        let mut fooled = 0;
+
+        for chunk in data.chunks(ATTACK_BATCH_SIZE) {
+            let data_points: Vec<&NNData> = chunk.iter().collect();
+            let before = self.predictions(batch_inputs(&data_points));
+            let after = self.predictions(self.attack_batch(&data_points, attack));
+
+            for ((before, after), data_point) in zip(zip(before, after), chunk) {
+                if before == data_point.label {
+                    correct += 1;
+                    if after != data_point.label {
+                        fooled += 1;
+                    }
+                }
+            }
+        }
+
+        AttackReport {
+            attack,
+            samples: data.len(),
+            correct,
+            fooled,
+        }
+    }
+
+    // the attacked inputs of a batch, one per row.
+    fn attack_batch(&self, batch: &[&NNData], attack: Attack) -> Matrix {
+        let original = batch_inputs(batch);
+        let targets = batch_targets(batch);
+        let epsilon = attack.epsilon();
+        let (step_size, steps) = match attack {
+            Attack::Fgsm { epsilon } => (epsilon, 1),
+            Attack::Pgd { step_size, steps, .. } => (step_size, steps),
+        };
+
+        let mut adversarial = original.clone();
+        for _ in 0..steps {
+            let gradients = self.input_gradients(adversarial.clone(), |tape, output| {
+                let losses = self.record_loss(tape, output, targets.clone());
+                tape.sum(losses)
+            });
+
+            // up the loss, then back into the ε-ball and the range of pixel values.
+            let values = zip(adversarial.get_mut_raw_slice(), gradients.get_raw_slice());
+            for ((x, g), original) in zip(values, original.get_raw_slice()) {
+                let sign = if *g > 0.0 { 1.0 } else if *g < 0.0 { -1.0 } else { 0.0 };
+                *x = (*x + step_size * sign)
+                    .clamp(original - epsilon, original + epsilon)
+                    .clamp(MIN_VALUE, MAX_VALUE);
+            }
+        }
+        adversarial
+    }
+
+    // the predicted label of every input (one per row).
+    fn predictions(&self, inputs: Matrix) -> Vec<usize> {
+        let cache = self.forward(inputs, None);
+        cache.output().iter_row_slices().map(argmax).collect()
+    }
+}
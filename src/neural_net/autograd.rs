@@ -0,0 +1,385 @@
+use super::math::{ F, Matrix, Tensor };
+use super::argmax;
+use super::activation::Activation;
+use super::layer::{ map_rows, zip_rows };
+
+use std::iter::zip;
+
+// `ln` treats smaller values as this, so it stays finite when an output saturates.
+pub const LOG_EPSILON: F = 1e-7;
+
+/// A matrix recorded on a `Tape`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Var(usize);
+
+// how a value on the tape was computed, from values recorded before it.
+#[derive(Debug)]
+enum Op {
+    /// An input or parameter.
+    Leaf,
+    Add(Var, Var),
+    Sub(Var, Var),
+    Mul(Var, Var),
+    Scale(Var, F),
+    Powf(Var, F),
+    MatMul(Var, Var),
+    MatMulTranspose(Var, Var),
+    Activation(Var, Activation),
+    Ln(Var),
+    Sum(Var),
+    SumRows(Var),
+    MaxRows(Var),
+    Mean(Var),
+    MeanColumns(Var),
+    Reshape(Var),
+    /// `None` is a 0 that isn't taken from anywhere.
+    Gather { a: Var, sample_size: usize, indices: Vec<Option<usize>> },
+}
+
+#[derive(Debug)]
+struct Node {
+    value: Matrix,
+    op: Op,
+}
+
+/// Reverse-mode automatic differentiation: every operation is computed straight away and
+/// recorded, so `backward` can then go through them in reverse to find the gradient of a
+/// result with respect to every matrix that went into it.
+///
+/// Inputs and constants are added with `leaf`, and whatever is being learned with
+/// `parameter`; nothing needs its derivative written by hand as long as it's built from
+/// the operations here.
+///
+/// Layers recording themselves also leave the batch statistics they used (batch norm's
+/// mean and variance) on the tape, for their running averages.
+#[derive(Debug, Default)]
+pub struct Tape {
+    nodes: Vec<Node>,
+    parameters: Vec<Var>,
+    batch_statistics: Vec<Vec<F>>,
+}
+
+/// The gradients found by `Tape::backward`, one per recorded value.
+#[derive(Debug)]
+pub struct TapeGradients {
+    gradients: Vec<Option<Matrix>>,
+}
+
+impl Tape {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(&mut self, value: Matrix, op: Op) -> Var {
+        self.nodes.push(Node { value, op });
+        Var(self.nodes.len() - 1)
+    }
+
+    /// Records an input or parameter.
+    pub fn leaf(&mut self, value: Matrix) -> Var {
+        self.push(value, Op::Leaf)
+    }
+
+    /// Records something learned, whose gradient is wanted; see `parameters`.
+    pub fn parameter(&mut self, value: Matrix) -> Var {
+        let var = self.leaf(value);
+        self.parameters.push(var);
+        var
+    }
+
+    /// Everything recorded with `parameter`, in the order it was.
+    pub fn parameters(&self) -> &[Var] {
+        &self.parameters
+    }
+
+    /// Keeps statistics of the batch (not recorded values, as they don't get gradients).
+    pub fn add_batch_statistics(&mut self, statistics: Vec<Vec<F>>) {
+        self.batch_statistics.extend(statistics);
+    }
+
+    /// Everything added with `add_batch_statistics`, in the order it was.
+    pub fn batch_statistics(&self) -> &[Vec<F>] {
+        &self.batch_statistics
+    }
+
+    pub fn value(&self, var: Var) -> &Matrix {
+        &self.nodes[var.0].value
+    }
+
+    /// Element-wise sum. `b` can also be a single row, which is added to every row of `a`
+    /// (like a bias).
+    pub fn add(&mut self, a: Var, b: Var) -> Var {
+        let value = broadcast_rows(self.value(a), self.value(b), |x, y| x + y);
+        self.push(value, Op::Add(a, b))
+    }
+
+    /// Element-wise difference; `b` can be a single row, as with `add`.
+    pub fn sub(&mut self, a: Var, b: Var) -> Var {
+        let value = broadcast_rows(self.value(a), self.value(b), |x, y| x - y);
+        self.push(value, Op::Sub(a, b))
+    }
+
+    /// Element-wise (Hadamard) product; `b` can be a single row, as with `add`.
+    pub fn mul(&mut self, a: Var, b: Var) -> Var {
+        let value = broadcast_rows(self.value(a), self.value(b), |x, y| x * y);
+        self.push(value, Op::Mul(a, b))
+    }
+
+    pub fn scale(&mut self, a: Var, scalar: F) -> Var {
+        let value = self.value(a) * scalar;
+        self.push(value, Op::Scale(a, scalar))
+    }
+
+    /// Raises every value to the power `exponent`.
+    pub fn powf(&mut self, a: Var, exponent: F) -> Var {
+        let mut value = self.value(a).clone();
+        value.apply_fn(|x| *x = x.powf(exponent));
+        self.push(value, Op::Powf(a, exponent))
+    }
+
+    /// Matrix product `a·b`.
+    pub fn matmul(&mut self, a: Var, b: Var) -> Var {
+        let value = self.value(a) * self.value(b);
+        self.push(value, Op::MatMul(a, b))
+    }
+
+    /// Matrix product with the transpose of `b`, `a·bᵀ`; with samples as rows and a weight
+    /// matrix of one row per output, that's every sample's outputs.
+    pub fn matmul_transpose(&mut self, a: Var, b: Var) -> Var {
+        let value = self.value(a).mul_transpose(self.value(b));
+        self.push(value, Op::MatMulTranspose(a, b))
+    }
+
+    /// Applies an activation function to each row separately.
+    pub fn activation(&mut self, a: Var, activation: Activation) -> Var {
+        let value = map_rows(self.value(a), |z| activation.apply(z));
+        self.push(value, Op::Activation(a, activation))
+    }
+
+    /// Element-wise natural logarithm of `max(x, LOG_EPSILON)`.
+    pub fn ln(&mut self, a: Var) -> Var {
+        let mut value = self.value(a).clone();
+        value.apply_fn(|x| *x = x.max(LOG_EPSILON).ln());
+        self.push(value, Op::Ln(a))
+    }
+
+    /// The sum of every value, as a 1×1 matrix.
+    pub fn sum(&mut self, a: Var) -> Var {
+        let value = Matrix::from_values(vec![self.value(a).get_raw_slice().iter().sum::<F>()], 1, 1);
+        self.push(value, Op::Sum(a))
+    }
+
+    /// The sum of each row, as a column.
+    pub fn sum_rows(&mut self, a: Var) -> Var {
+        let a_value = self.value(a);
+        let value = Matrix::from_values(a_value.iter_row_slices().map(|row| row.iter().sum()).collect::<Vec<F>>(), a_value.m(), 1);
+        self.push(value, Op::SumRows(a))
+    }
+
+    /// The largest value of each row, as a column.
+    pub fn max_rows(&mut self, a: Var) -> Var {
+        let a_value = self.value(a);
+        let value = Matrix::from_values(a_value.iter_row_slices().map(|row| row[argmax(row)]).collect::<Vec<F>>(), a_value.m(), 1);
+        self.push(value, Op::MaxRows(a))
+    }
+
+    /// The mean of every value, as a 1×1 matrix.
+    pub fn mean(&mut self, a: Var) -> Var {
+        let values = self.value(a).get_raw_slice();
+        let value = Matrix::from_values(vec![values.iter().sum::<F>() / values.len() as F], 1, 1);
+        self.push(value, Op::Mean(a))
+    }
+
+    /// The mean of each column (over a batch, with samples as rows), as a row.
+    pub fn mean_columns(&mut self, a: Var) -> Var {
+        let value = Tensor::from(self.value(a)).mean_axis(0).to_matrix();
+        self.push(value, Op::MeanColumns(a))
+    }
+
+    /// The same values, read row by row into an `m`×`n` matrix.
+    pub fn reshape(&mut self, a: Var, m: usize, n: usize) -> Var {
+        let value = Matrix::from_values(self.value(a).get_raw_values(), m, n);
+        self.push(value, Op::Reshape(a))
+    }
+
+    /// Picks values out of each sample and lays them out in rows of `n`, for rearranging
+    /// images (into the windows of a convolution or pooling, say).
+    ///
+    /// The values of `a` are taken `sample_size` at a time (one sample is a row, or several),
+    /// and each sample gives the values at `indices` in it, in that order, with `None` giving
+    /// 0. So the result has `indices.len() / n` rows per sample.
+    pub fn gather(&mut self, a: Var, sample_size: usize, indices: Vec<Option<usize>>, n: usize) -> Var {
+        let values = self.value(a).get_raw_slice();
+        assert!(values.len().is_multiple_of(sample_size) && indices.len().is_multiple_of(n));
+
+        let gathered: Vec<F> = values.chunks(sample_size)
+            .flat_map(|sample| indices.iter().map(|index| index.map_or(0.0, |index| sample[index])))
+            .collect();
+        let m = gathered.len() / n;
+
+        self.push(Matrix::from_values(gathered, m, n), Op::Gather { a, sample_size, indices })
+    }
+
+    /// The gradient of `output`, which has to be a single value (like a loss), with respect
+    /// to every value recorded before it.
+    pub fn backward(&self, output: Var) -> TapeGradients {
+        let value = self.value(output);
+        if value.m() != 1 || value.n() != 1 {
+            panic!("can only backpropagate from a single value, not a {}×{} matrix!", value.m(), value.n());
+        }
+
+        let mut gradients = vec![None; self.nodes.len()];
+        gradients[output.0] = Some(Matrix::from_values(vec![1.0], 1, 1));
+
+        // every operation only uses values recorded before it, so going backwards means a
+        // value's gradient is complete before it's passed on.
+        for i in (0..=output.0).rev() {
+            let Some(grad) = gradients[i].take() else {
+                continue;
+            };
+
+            let mut add = |var: Var, g: Matrix| match &mut gradients[var.0] {
+                Some(existing) => *existing += &g,
+                none => *none = Some(g),
+            };
+
+            match &self.nodes[i].op {
+                Op::Leaf => {}
+                Op::Add(a, b) => {
+                    add(*a, grad.clone());
+                    add(*b, unbroadcast_rows(&grad, self.value(*b)));
+                }
+                Op::Sub(a, b) => {
+                    add(*a, grad.clone());
+                    add(*b, &unbroadcast_rows(&grad, self.value(*b)) * -1.0);
+                }
+                Op::Mul(a, b) => {
+                    add(*a, broadcast_rows(&grad, self.value(*b), |g, y| g * y));
+                    add(*b, unbroadcast_rows(&elementwise(&grad, self.value(*a), |g, x| g * x), self.value(*b)));
+                }
+                Op::Scale(a, scalar) => add(*a, &grad * *scalar),
+                Op::Powf(a, exponent) => {
+                    add(*a, elementwise(&grad, self.value(*a), |g, x| g * exponent * x.powf(exponent - 1.0)));
+                }
+                Op::MatMul(a, b) => {
+                    add(*a, grad.mul_transpose(self.value(*b)));
+                    add(*b, self.value(*a).tmul(&grad));
+                }
+                Op::MatMulTranspose(a, b) => {
+                    add(*a, &grad * self.value(*b));
+                    add(*b, grad.tmul(self.value(*a)));
+                }
+                Op::Activation(a, activation) => {
+                    add(*a, zip_rows(self.value(*a), &grad, |z, g| activation.backpropagate(z, g)));
+                }
+                // as if the clamped values were `LOG_EPSILON`, so they still get pushed up.
+                Op::Ln(a) => add(*a, elementwise(&grad, self.value(*a), |g, x| g / x.max(LOG_EPSILON))),
+                Op::Sum(a) => {
+                    let a_value = self.value(*a);
+                    add(*a, Matrix::from_values(vec![grad.get_raw_slice()[0]; a_value.m() * a_value.n()], a_value.m(), a_value.n()));
+                }
+                // pooling has a row per window, so these fill the gradient in place rather than
+                // building every row separately.
+                Op::SumRows(a) => {
+                    let a_value = self.value(*a);
+                    let mut a_grad = Matrix::new(a_value.m(), a_value.n());
+                    for (row_grad, g) in zip(a_grad.get_mut_raw_slice().chunks_mut(a_value.n()), grad.get_raw_slice()) {
+                        row_grad.fill(*g);
+                    }
+                    add(*a, a_grad);
+                }
+                // only the largest value of each row made it through.
+                Op::MaxRows(a) => {
+                    let a_value = self.value(*a);
+                    let mut a_grad = Matrix::new(a_value.m(), a_value.n());
+                    let rows = zip(a_grad.get_mut_raw_slice().chunks_mut(a_value.n()), a_value.iter_row_slices());
+                    for ((row_grad, row), g) in zip(rows, grad.get_raw_slice()) {
+                        row_grad[argmax(row)] = *g;
+                    }
+                    add(*a, a_grad);
+                }
+                Op::Mean(a) => {
+                    let a_value = self.value(*a);
+                    let count = a_value.m() * a_value.n();
+                    add(*a, Matrix::from_values(vec![grad.get_raw_slice()[0] / count as F; count], a_value.m(), a_value.n()));
+                }
+                Op::MeanColumns(a) => {
+                    let rows = self.value(*a).m();
+                    add(*a, map_rows(self.value(*a), |_| grad.get_row_slice(0).iter().map(|g| g / rows as F).collect()));
+                }
+                Op::Reshape(a) => {
+                    let a_value = self.value(*a);
+                    add(*a, Matrix::from_values(grad.get_raw_values(), a_value.m(), a_value.n()));
+                }
+                // every value goes back to where it was picked from.
+                Op::Gather { a, sample_size, indices } => {
+                    let a_value = self.value(*a);
+                    let mut a_grad = Matrix::new(a_value.m(), a_value.n());
+
+                    let samples = zip(a_grad.get_mut_raw_slice().chunks_mut(*sample_size), grad.get_raw_slice().chunks(indices.len()));
+                    for (sample_grad, grad) in samples {
+                        for (index, g) in zip(indices, grad) {
+                            if let Some(index) = index {
+                                sample_grad[*index] += g;
+                            }
+                        }
+                    }
+                    add(*a, a_grad);
+                }
+            }
+
+            gradients[i] = Some(grad);
+        }
+
+        TapeGradients { gradients }
+    }
+}
+
+impl TapeGradients {
+    /// The gradient with respect to `var`, or `None` if the result didn't depend on it.
+    pub fn get(&self, var: Var) -> Option<&Matrix> {
+        self.gradients[var.0].as_ref()
+    }
+}
+
+fn elementwise(a: &Matrix, b: &Matrix, f: impl Fn(F, F) -> F) -> Matrix {
+    if a.m() != b.m() || a.n() != b.n() {
+        panic!("Dimension mismatch between {}×{} and {}×{} matrices!", a.m(), a.n(), b.m(), b.n());
+    }
+
+    let values: Vec<F> = zip(a.get_raw_slice(), b.get_raw_slice()).map(|(&x, &y)| f(x, y)).collect();
+    Matrix::from_values(values, a.m(), a.n())
+}
+
+// like `elementwise`, but a single row `b` is used for every row of `a`.
+fn broadcast_rows(a: &Matrix, b: &Matrix, f: impl Fn(F, F) -> F) -> Matrix {
+    if b.m() != 1 || a.m() == 1 {
+        return elementwise(a, b, f);
+    }
+    if a.n() != b.n() {
+        panic!("Dimension mismatch between {}×{} and {}×{} matrices!", a.m(), a.n(), b.m(), b.n());
+    }
+
+    // a convolution's bias goes onto a row per output position, so the rows aren't built
+    // separately.
+    let values: Vec<F> = a.iter_row_slices()
+        .flat_map(|row| zip(row, b.get_row_slice(0)).map(|(&x, &y)| f(x, y)))
+        .collect();
+    Matrix::from_values(values, a.m(), a.n())
+}
+
+// the gradient with respect to `b` of a `broadcast_rows` result: summed over the rows it
+// was used for.
+fn unbroadcast_rows(grad: &Matrix, b: &Matrix) -> Matrix {
+    if b.m() == grad.m() {
+        return grad.clone();
+    }
+
+    let mut sum = vec![0.0; grad.n()];
+    for row in grad.iter_row_slices() {
+        for (sum, g) in zip(&mut sum, row) {
+            *sum += g;
+        }
+    }
+    Matrix::from_values(sum, 1, grad.n())
+}
@@ -0,0 +1,123 @@
+use super::math::{ F, Matrix };
+use super::layer::Layer;
+use super::autograd::{ Tape, Var };
+use super::model_file::{ self, checked_size, read_floats, read_u32, write_floats, write_u32 };
+
+use rand::RngCore;
+
+use std::io::{ self, Read, Write };
+
+// added to the variance so constant features don't divide by 0.
+const EPSILON: F = 1e-5;
+
+/// Batch normalization of a layer's pre-activations: every feature is shifted and scaled
+/// to mean 0 and variance 1 over the batch, then given a learned scale (`gamma`) and
+/// shift (`beta`).
+///
+/// Predictions (and training batches of a single sample, which have no variance) use
+/// running averages of the batch statistics instead, so they don't depend on what else
+/// is in the batch.
+#[derive(Debug, Clone)]
+pub struct BatchNorm {
+    gamma: Vec<F>,
+    beta: Vec<F>,
+    running_mean: Vec<F>,
+    running_variance: Vec<F>,
+}
+
+impl BatchNorm {
+    /// Starts as the identity: scale 1, shift 0, running mean 0 and variance 1.
+    pub fn new(features: usize) -> Self {
+        Self {
+            gamma: vec![1.0; features],
+            beta: vec![0.0; features],
+            running_mean: vec![0.0; features],
+            running_variance: vec![1.0; features],
+        }
+    }
+
+    pub fn features(&self) -> usize {
+        self.gamma.len()
+    }
+
+    pub(super) fn read_from(reader: &mut dyn Read) -> io::Result<Self> {
+        let features = checked_size(read_u32(reader)? as usize, 1)?;
+
+        let mut bn = Self::new(features);
+        for values in [&mut bn.gamma, &mut bn.beta, &mut bn.running_mean, &mut bn.running_variance] {
+            *values = read_floats(reader, features)?;
+        }
+        Ok(bn)
+    }
+}
+
+impl Layer for BatchNorm {
+    fn name(&self) -> String {
+        format!("Batch Norm {}", self.features())
+    }
+
+    fn sizes(&self) -> Option<(usize, usize)> {
+        Some((self.features(), self.features()))
+    }
+
+    // normalizes with the statistics of the batch itself while training, unless there's
+    // only one sample.
+    fn forward(&self, tape: &mut Tape, x: Var, rng: Option<&mut dyn RngCore>) -> Var {
+        let batch_size = tape.value(x).m();
+        let row = |values: Vec<F>| Matrix::from_values(values, 1, self.features());
+
+        let gamma = tape.parameter(row(self.gamma.clone()));
+        let beta = tape.parameter(row(self.beta.clone()));
+
+        let normalized = if rng.is_some() && batch_size > 1 {
+            let mean = tape.mean_columns(x);
+            let centred = tape.sub(x, mean);
+            let squared = tape.mul(centred, centred);
+            let variance = tape.mean_columns(squared);
+
+            // the running variance estimates the population, so it gets Bessel's correction.
+            let correction = batch_size as F / (batch_size - 1) as F;
+            let unbiased = tape.value(variance).get_raw_slice().iter().map(|v| v * correction).collect();
+            tape.add_batch_statistics(vec![tape.value(mean).get_raw_values(), unbiased]);
+
+            let epsilon = tape.leaf(row(vec![EPSILON; self.features()]));
+            let variance = tape.add(variance, epsilon);
+            let inverse_std = tape.powf(variance, -0.5);
+            tape.mul(centred, inverse_std)
+        } else {
+            // the running statistics are constants, so normalizing is just a shift and a scale.
+            let mean = tape.leaf(row(self.running_mean.clone()));
+            let inverse_std = tape.leaf(row(self.running_variance.iter().map(|v| 1.0 / (v + EPSILON).sqrt()).collect()));
+            let centred = tape.sub(x, mean);
+            tape.mul(centred, inverse_std)
+        };
+
+        let scaled = tape.mul(normalized, gamma);
+        tape.add(scaled, beta)
+    }
+
+    fn parameters(&self) -> Vec<&[F]> {
+        vec![&self.gamma, &self.beta]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut [F]> {
+        vec![&mut self.gamma, &mut self.beta]
+    }
+
+    fn statistics(&self) -> Vec<&[F]> {
+        vec![&self.running_mean, &self.running_variance]
+    }
+
+    fn statistics_mut(&mut self) -> Vec<&mut [F]> {
+        vec![&mut self.running_mean, &mut self.running_variance]
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&[model_file::BATCH_NORM])?;
+        write_u32(writer, self.features() as u32)?;
+        for values in [&self.gamma, &self.beta, &self.running_mean, &self.running_variance] {
+            write_floats(writer, values)?;
+        }
+        Ok(())
+    }
+}
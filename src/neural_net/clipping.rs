@@ -0,0 +1,40 @@
+use super::math::F;
+
+/// Limits on the gradient of each step, so one bad batch (or too high a learning rate)
+/// can't throw the weights far off.
+///
+/// Values are clamped first, then the whole gradient is scaled down if its norm is still
+/// too large. Only the gradient of the loss is clipped, not the regularization's.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct GradientClipping {
+    /// Clamp every gradient value to `[-value, value]`.
+    pub value: Option<F>,
+    /// Scale the gradient so its L2 norm, over every parameter of the net, is at most this.
+    pub norm: Option<F>,
+}
+
+impl GradientClipping {
+    /// One gradient value, clamped.
+    pub fn clamp(&self, g: F) -> F {
+        match self.value {
+            Some(value) => g.clamp(-value, value),
+            None => g,
+        }
+    }
+
+    /// What every (clamped) gradient value has to be multiplied by to keep the norm within
+    /// bounds: 1 unless it's too large.
+    pub fn norm_scale<'a>(&self, gradients: impl IntoIterator<Item = &'a [F]>) -> F {
+        let Some(max_norm) = self.norm else {
+            return 1.0;
+        };
+
+        let norm = gradients.into_iter()
+            .flatten()
+            .map(|&g| self.clamp(g).powi(2))
+            .sum::<F>()
+            .sqrt();
+
+        if norm > max_norm { max_norm / norm } else { 1.0 }
+    }
+}
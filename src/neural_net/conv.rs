@@ -0,0 +1,201 @@
+use super::math::{ F, Matrix };
+use super::layer::Layer;
+use super::init::Initialization;
+use super::autograd::{ Tape, Var };
+use super::model_file::{ self, checked_size, read_floats, read_u32, write_floats, write_u32 };
+
+use rand::RngCore;
+
+use std::fmt;
+use std::io::{ self, Read, Write };
+
+/// How the convolution and pooling layers see one sample's values: `channels` images of
+/// `height`×`width`, stored channel by channel, each row by row.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shape {
+    pub channels: usize,
+    pub height: usize,
+    pub width: usize,
+}
+
+impl Shape {
+    pub fn new(channels: usize, height: usize, width: usize) -> Self {
+        Self {
+            channels,
+            height,
+            width,
+        }
+    }
+
+    /// Number of values in one sample.
+    pub fn size(&self) -> usize {
+        self.channels * self.height * self.width
+    }
+
+    pub(super) fn read_from(reader: &mut dyn Read) -> io::Result<Self> {
+        let shape = Self::new(read_u32(reader)? as usize, read_u32(reader)? as usize, read_u32(reader)? as usize);
+        checked_size(checked_size(shape.channels, shape.height)?, shape.width)?;
+        Ok(shape)
+    }
+
+    pub(super) fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_u32(writer, self.channels as u32)?;
+        write_u32(writer, self.height as u32)?;
+        write_u32(writer, self.width as u32)
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}×{}×{}", self.channels, self.height, self.width)
+    }
+}
+
+/// 2D convolution: every output channel is a `kernel_size`×`kernel_size` filter over all input
+/// channels, slid across the (zero-padded) input `stride` pixels at a time, plus a bias.
+///
+/// Each sample is unrolled into a matrix with one row per output position holding the input
+/// values under the filter there (im2col), so the whole convolution is one matrix multiply.
+#[derive(Debug, Clone)]
+pub struct Conv2D {
+    input: Shape,
+    kernel_size: usize,
+    stride: usize,
+    padding: usize,
+    /// One row per output channel, one column per value under the filter.
+    weights: Matrix,
+    bias: Vec<F>,
+}
+
+impl Conv2D {
+    /// A layer with all weights 0; see `initialize`.
+    pub fn new(input: Shape, out_channels: usize, kernel_size: usize, stride: usize, padding: usize) -> Self {
+        assert!(stride > 0);
+        assert!(kernel_size > 0 && kernel_size <= input.height.min(input.width) + 2 * padding);
+
+        Self {
+            input,
+            kernel_size,
+            stride,
+            padding,
+            weights: Matrix::new(out_channels, input.channels * kernel_size * kernel_size),
+            bias: vec![0.0; out_channels],
+        }
+    }
+
+    pub fn output_shape(&self) -> Shape {
+        let size = |input: usize| (input + 2 * self.padding - self.kernel_size) / self.stride + 1;
+        Shape::new(self.weights.m(), size(self.input.height), size(self.input.width))
+    }
+
+    // for each output position and each value under the filter there: where that value is
+    // in the sample, or `None` if it's in the padding.
+    fn for_each_input(&self, mut f: impl FnMut(usize, usize, Option<usize>)) {
+        let output = self.output_shape();
+        let k = self.kernel_size;
+
+        for oy in 0..output.height {
+            for ox in 0..output.width {
+                let position = oy * output.width + ox;
+
+                for c in 0..self.input.channels {
+                    for ky in 0..k {
+                        for kx in 0..k {
+                            let column = (c * k + ky) * k + kx;
+
+                            let y = (oy * self.stride + ky).checked_sub(self.padding).filter(|&y| y < self.input.height);
+                            let x = (ox * self.stride + kx).checked_sub(self.padding).filter(|&x| x < self.input.width);
+
+                            let index = y.zip(x).map(|(y, x)| (c * self.input.height + y) * self.input.width + x);
+                            f(position, column, index);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // for `Tape::gather`: where each value of `im2col` comes from in a sample.
+    fn im2col_indices(&self) -> Vec<Option<usize>> {
+        let output = self.output_shape();
+        let mut indices = vec![None; output.height * output.width * self.weights.n()];
+
+        self.for_each_input(|position, column, index| indices[position * self.weights.n() + column] = index);
+
+        indices
+    }
+
+    pub(super) fn read_from(reader: &mut dyn Read) -> io::Result<Self> {
+        let input = Shape::read_from(reader)?;
+        let out_channels = read_u32(reader)? as usize;
+        let kernel_size = read_u32(reader)? as usize;
+        let stride = read_u32(reader)? as usize;
+        let padding = read_u32(reader)? as usize;
+
+        if stride == 0 || kernel_size == 0 || kernel_size > input.height.min(input.width) + 2 * padding {
+            return Err(model_file::invalid_data("convolution doesn't fit its input"));
+        }
+        checked_size(out_channels, checked_size(input.channels, checked_size(kernel_size, kernel_size)?)?)?;
+
+        let mut conv = Self::new(input, out_channels, kernel_size, stride, padding);
+        conv.weights = Matrix::from_values(read_floats(reader, conv.weights.m() * conv.weights.n())?, conv.weights.m(), conv.weights.n());
+        conv.bias = read_floats(reader, out_channels)?;
+        Ok(conv)
+    }
+}
+
+impl Layer for Conv2D {
+    fn name(&self) -> String {
+        format!("Conv2D {k}×{k} {} → {}", self.input, self.output_shape(), k = self.kernel_size)
+    }
+
+    fn sizes(&self) -> Option<(usize, usize)> {
+        Some((self.input.size(), self.output_shape().size()))
+    }
+
+    fn forward(&self, tape: &mut Tape, input: Var, _rng: Option<&mut dyn RngCore>) -> Var {
+        let output = self.output_shape();
+        let positions = output.height * output.width;
+
+        let weights = tape.parameter(self.weights.clone());
+        let bias = tape.parameter(Matrix::from_values(self.bias.clone(), 1, self.bias.len()));
+
+        let columns = tape.gather(input, self.input.size(), self.im2col_indices(), self.weights.n());
+        let y = tape.matmul_transpose(columns, weights);
+        let y = tape.add(y, bias);
+
+        // `y` has one row per output position (of every sample) and one column per output
+        // channel; a sample's output goes channel by channel instead.
+        let channels_first = (0..output.channels)
+            .flat_map(|channel| (0..positions).map(move |position| Some(position * output.channels + channel)))
+            .collect();
+        tape.gather(y, positions * output.channels, channels_first, output.size())
+    }
+
+    fn parameters(&self) -> Vec<&[F]> {
+        vec![self.weights.get_raw_slice(), &self.bias]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut [F]> {
+        vec![self.weights.get_mut_raw_slice(), &mut self.bias]
+    }
+
+    // each output value sees c·k·k inputs, and each input reaches (about) k·k positions of
+    // every output channel.
+    fn initialize(&mut self, initialization: Initialization, rng: &mut dyn RngCore) {
+        let fan_in = self.weights.n();
+        let fan_out = self.weights.m() * self.kernel_size * self.kernel_size;
+        initialization.initialize(&mut self.weights, fan_in, fan_out, rng);
+        self.bias.iter_mut().for_each(|b| *b = 0.0);
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&[model_file::CONV_2D])?;
+        self.input.write_to(writer)?;
+        for value in [self.weights.m(), self.kernel_size, self.stride, self.padding] {
+            write_u32(writer, value as u32)?;
+        }
+        write_floats(writer, self.weights.get_raw_slice())?;
+        write_floats(writer, &self.bias)
+    }
+}
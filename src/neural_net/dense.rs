@@ -0,0 +1,82 @@
+use super::math::{ F, Matrix };
+use super::layer::Layer;
+use super::init::Initialization;
+use super::autograd::{ Tape, Var };
+use super::model_file::{ self, checked_size, read_floats, read_u32, write_floats, write_u32 };
+
+use rand::RngCore;
+
+use std::io::{ self, Read, Write };
+
+/// Fully connected layer: every output is a weighted sum of every input, `W·x`.
+#[derive(Debug, Clone)]
+pub struct Dense {
+    /// One row per output, one column per input.
+    weights: Matrix,
+}
+
+impl Dense {
+    /// A layer with all weights 0; see `initialize`.
+    pub fn new(inputs: usize, outputs: usize) -> Self {
+        Self {
+            weights: Matrix::new(outputs, inputs),
+        }
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.weights.n()
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.weights.m()
+    }
+
+    /// The weights of every output on the inputs, one slice per output.
+    pub fn unit_weights(&self) -> impl Iterator<Item = &[F]> {
+        self.weights.iter_row_slices()
+    }
+
+    pub(super) fn read_from(reader: &mut dyn Read) -> io::Result<Self> {
+        let m = read_u32(reader)? as usize;
+        let n = read_u32(reader)? as usize;
+
+        Ok(Self {
+            weights: Matrix::from_values(read_floats(reader, checked_size(m, n)?)?, m, n),
+        })
+    }
+}
+
+impl Layer for Dense {
+    fn name(&self) -> String {
+        format!("Dense {} → {}", self.inputs(), self.outputs())
+    }
+
+    fn sizes(&self) -> Option<(usize, usize)> {
+        Some((self.inputs(), self.outputs()))
+    }
+
+    fn forward(&self, tape: &mut Tape, input: Var, _rng: Option<&mut dyn RngCore>) -> Var {
+        let weights = tape.parameter(self.weights.clone());
+        tape.matmul_transpose(input, weights)
+    }
+
+    fn parameters(&self) -> Vec<&[F]> {
+        vec![self.weights.get_raw_slice()]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut [F]> {
+        vec![self.weights.get_mut_raw_slice()]
+    }
+
+    fn initialize(&mut self, initialization: Initialization, rng: &mut dyn RngCore) {
+        let (fan_in, fan_out) = (self.inputs(), self.outputs());
+        initialization.initialize(&mut self.weights, fan_in, fan_out, rng);
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&[model_file::DENSE])?;
+        write_u32(writer, self.weights.m() as u32)?;
+        write_u32(writer, self.weights.n() as u32)?;
+        write_floats(writer, self.weights.get_raw_slice())
+    }
+}
@@ -0,0 +1,63 @@
+use super::math::{ F, Matrix };
+use super::layer::Layer;
+use super::autograd::{ Tape, Var };
+use super::model_file::{ self, read_floats, write_floats };
+
+use rand::{ Rng, RngCore };
+
+use std::io::{ self, Read, Write };
+
+/// Zeroes each value with some probability while training. Kept values are scaled up by
+/// `1 / (1 - probability)` (inverted dropout), so inference needs no rescaling and just
+/// passes everything through.
+#[derive(Debug, Clone)]
+pub struct Dropout {
+    probability: F,
+}
+
+impl Dropout {
+    pub fn new(probability: F) -> Self {
+        assert!((0.0..1.0).contains(&probability));
+        Self {
+            probability,
+        }
+    }
+
+    pub fn probability(&self) -> F {
+        self.probability
+    }
+
+    pub(super) fn read_from(reader: &mut dyn Read) -> io::Result<Self> {
+        let probability = read_floats(reader, 1)?[0];
+        if !(0.0..1.0).contains(&probability) {
+            return Err(model_file::invalid_data("dropout probability out of range"));
+        }
+        Ok(Self::new(probability))
+    }
+}
+
+impl Layer for Dropout {
+    fn name(&self) -> String {
+        format!("Dropout {}", self.probability)
+    }
+
+    fn forward(&self, tape: &mut Tape, input: Var, rng: Option<&mut dyn RngCore>) -> Var {
+        match rng {
+            Some(rng) if self.probability > 0.0 => {
+                let keep_scale = 1.0 / (1.0 - self.probability);
+                let mut mask = Matrix::new(tape.value(input).m(), tape.value(input).n());
+                mask.apply_fn(|m| *m = if rng.random::<F>() < self.probability { 0.0 } else { keep_scale });
+
+                // a constant, so dropped values get no gradient.
+                let mask = tape.leaf(mask);
+                tape.mul(input, mask)
+            }
+            _ => input,
+        }
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&[model_file::DROPOUT])?;
+        write_floats(writer, &[self.probability])
+    }
+}
@@ -0,0 +1,88 @@
+
+use super::{ NeuralNet, NNData, Mode };
+use super::math::F;
+
+use rand::rng;
+
+// gradients smaller than this are compared absolutely rather than relatively, otherwise
+// f32 rounding in the finite differences swamps weights that barely matter.
+const RELATIVE_ERROR_FLOOR: F = 0.001;
+
+/// How well backpropagation agreed with finite differences for one layer's parameters.
+#[derive(Debug, Clone)]
+pub struct LayerGradientCheck {
+    pub name: String,
+    pub checked: usize,
+    pub max_relative_error: F,
+    pub mean_relative_error: F,
+}
+
+/// One entry per layer with parameters.
+#[derive(Debug, Clone)]
+pub struct GradientCheckReport {
+    pub layers: Vec<LayerGradientCheck>,
+}
+
+impl GradientCheckReport {
+    pub fn max_relative_error(&self) -> F {
+        self.layers.iter().fold(0.0, |max, layer| max.max(layer.max_relative_error))
+    }
+}
+
+impl NeuralNet {
+    /// Compares the analytic (backprop) gradient of the loss for `data_point` with the centred
+    /// finite difference `(L(w + ε) - L(w - ε)) / 2ε` of every parameter.
+    ///
+    /// Checking every parameter costs two forward passes each, so at most `max_checks_per_layer`
+    /// parameters, evenly spread through the layer, are checked. The check runs in inference mode,
+    /// since dropout would make the loss random.
+    pub fn gradient_check(&self, data_point: &NNData, epsilon: F, max_checks_per_layer: usize) -> GradientCheckReport {
+        let mut nn = self.clone();
+        nn.set_mode(Mode::Inference);
+
+        let mut gradients = nn.zero_gradients();
+        nn.accumulate_gradients(&[data_point], &mut gradients, &mut rng());
+
+        let layers = (0..self.layers.layers().len())
+            .filter(|&layer| !gradients.layers[layer].parameters.is_empty())
+            .map(|layer| {
+                // every parameter of the layer, as (which slice, index in it).
+                let indices: Vec<(usize, usize)> = gradients.layers[layer].parameters.iter()
+                    .enumerate()
+                    .flat_map(|(p, values)| (0..values.len()).map(move |i| (p, i)))
+                    .collect();
+                let step = indices.len().div_ceil(max_checks_per_layer.max(1));
+
+                let relative_errors: Vec<F> = indices.iter().step_by(step)
+                    .map(|&(p, i)| {
+                        let w = self.layers.layers()[layer].parameters()[p][i];
+
+                        nn.layers.layers_mut()[layer].parameters_mut()[p][i] = w + epsilon;
+                        let loss_plus = nn.loss(data_point);
+                        nn.layers.layers_mut()[layer].parameters_mut()[p][i] = w - epsilon;
+                        let loss_minus = nn.loss(data_point);
+                        nn.layers.layers_mut()[layer].parameters_mut()[p][i] = w;
+
+                        let numerical = (loss_plus - loss_minus) / (2.0 * epsilon);
+                        let analytic = gradients.layers[layer].parameters[p][i];
+
+                        (numerical - analytic).abs()
+                            / numerical.abs().max(analytic.abs()).max(RELATIVE_ERROR_FLOOR)
+                    })
+                    .collect();
+
+                LayerGradientCheck {
+                    name: self.layers.layers()[layer].name(),
+                    checked: relative_errors.len(),
+                    max_relative_error: relative_errors.iter().fold(0.0, |max, &e| max.max(e)),
+                    mean_relative_error: relative_errors.iter().sum::<F>() / relative_errors.len() as F,
+                }
+            })
+            .collect();
+
+        GradientCheckReport {
+            layers,
+        }
+    }
+
+}
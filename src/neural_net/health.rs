@@ -0,0 +1,53 @@
+use super::{ NeuralNet, NNData, scale_and_normalize_data };
+use super::math::F;
+
+use std::fmt;
+
+/// Where a NaN or infinity turned up, once training has blown up.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NonFinite {
+    Loss,
+    /// The parameters or running statistics of a layer, by index and name.
+    Weights(usize, String),
+    /// The output of a layer, by index and name.
+    Activations(usize, String),
+}
+
+impl fmt::Display for NonFinite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NonFinite::Loss => write!(f, "NaN/Inf in the loss"),
+            NonFinite::Weights(layer, name) => write!(f, "NaN/Inf in the weights of layer {layer} ({name})"),
+            NonFinite::Activations(layer, name) => write!(f, "NaN/Inf in the output of layer {layer} ({name})"),
+        }
+    }
+}
+
+impl NeuralNet {
+    /// Looks for NaN or infinite values: in every layer's weights, then in every layer's output
+    /// for `probe`, then in `losses` (from training). Gives the first place one is found, so a
+    /// layer is blamed when there's one to blame, and the earliest one at that.
+    pub fn find_non_finite(&self, losses: &[F], probe: &NNData) -> Option<NonFinite> {
+        let is_finite = |values: Vec<&[F]>| values.iter().all(|values| values.iter().all(|x| x.is_finite()));
+
+        for (i, layer) in self.layers.layers().iter().enumerate() {
+            if !is_finite(layer.parameters()) || !is_finite(layer.statistics()) {
+                return Some(NonFinite::Weights(i, layer.name()));
+            }
+        }
+
+        // activations[0] is the input.
+        let cache = self.forward_cache(scale_and_normalize_data(&probe.data));
+        for (i, layer) in self.layers.layers().iter().enumerate() {
+            if !is_finite(vec![cache.activation(i + 1).get_raw_slice()]) {
+                return Some(NonFinite::Activations(i, layer.name()));
+            }
+        }
+
+        if !losses.iter().all(|x| x.is_finite()) {
+            return Some(NonFinite::Loss);
+        }
+
+        None
+    }
+}
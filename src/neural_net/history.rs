@@ -0,0 +1,181 @@
+use super::math::F;
+
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// Min, mean and max of a run of consecutive values in a `History`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bucket {
+    /// Index of the first value in the bucket.
+    pub start: usize,
+    pub count: usize,
+    pub min: F,
+    pub max: F,
+    // in f64, so a bucket of millions of values still has an accurate mean.
+    sum: f64,
+}
+
+/// A series of values (like the training loss of every sample) that can grow forever
+/// without getting more expensive to keep or to plot.
+///
+/// Values are kept as min/mean/max buckets at several resolutions: level `k` has buckets of
+/// `2^k` values, but only its `capacity` most recent ones. Whenever the coarsest level fills
+/// up, a level half as detailed is started from it, so there's always a level covering every
+/// value, and the most recent values are kept in the most detail. Memory and the cost of a
+/// query grow with the log of the number of values.
+#[derive(Debug, Clone)]
+pub struct History {
+    capacity: usize,
+    levels: Vec<VecDeque<Bucket>>,
+    len: usize,
+}
+
+impl Bucket {
+    fn new(start: usize, value: F) -> Self {
+        Self {
+            start,
+            count: 1,
+            min: value,
+            max: value,
+            sum: value as f64,
+        }
+    }
+
+    fn add(&mut self, value: F) {
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value as f64;
+    }
+
+    fn merge(&mut self, other: &Bucket) {
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+    }
+
+    pub fn mean(&self) -> F {
+        (self.sum / self.count as f64) as F
+    }
+
+    /// Index one past the last value in the bucket.
+    pub fn end(&self) -> usize {
+        self.start + self.count
+    }
+}
+
+impl History {
+    /// `capacity` is the number of buckets kept at each resolution.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity >= 2);
+
+        Self {
+            capacity,
+            levels: vec![VecDeque::new()],
+            len: 0,
+        }
+    }
+
+    /// How many values have been pushed.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, value: F) {
+        let index = self.len;
+        self.len += 1;
+
+        for (k, level) in self.levels.iter_mut().enumerate() {
+            match level.back_mut() {
+                Some(bucket) if bucket.start >> k == index >> k => bucket.add(value),
+                _ => level.push_back(Bucket::new(index, value)),
+            }
+        }
+
+        // the coarsest level has to cover everything, so it gets a coarser one before it can
+        // lose its oldest buckets.
+        let coarsest = self.levels.len() - 1;
+        if self.levels[coarsest].len() > self.capacity {
+            let coarser = merge_pairs(&self.levels[coarsest], coarsest + 1);
+            self.levels.push(coarser);
+        }
+
+        let capacity = self.capacity;
+        let coarsest = self.levels.len() - 1;
+        for level in &mut self.levels[..coarsest] {
+            while level.len() > capacity {
+                level.pop_front();
+            }
+        }
+    }
+
+    /// Buckets covering `range` (of value indices), from the most detailed level that has
+    /// all of it in fewer than `max_buckets` buckets, or else from the coarsest.
+    pub fn buckets(&self, range: Range<usize>, max_buckets: usize) -> Vec<Bucket> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+
+        let range = range.start.min(self.len)..range.end.min(self.len);
+
+        let level = self.levels.iter()
+            .enumerate()
+            .find(|(k, level)| {
+                level.front().is_some_and(|first| first.start <= range.start)
+                    && range.len().div_ceil(1 << k) < max_buckets
+            })
+            .map_or(self.levels.last().unwrap(), |(_, level)| level);
+
+        level.iter()
+            .filter(|bucket| bucket.end() > range.start && bucket.start < range.end)
+            .copied()
+            .collect()
+    }
+
+    /// Exponential moving average of the means of `buckets` (from this history): as if each
+    /// value kept `smoothing` of the average before it, with every value in a bucket at the
+    /// bucket's mean. It carries on from the values before the first bucket, so a zoomed-in
+    /// stretch is smoothed the same as the whole run; the very first value starts it.
+    pub fn exponential_moving_average(&self, buckets: &[Bucket], smoothing: f64) -> Vec<F> {
+        let mut average: Option<f64> = None;
+        let mut step = |bucket: &Bucket| -> F {
+            let mean = bucket.mean() as f64;
+            let keep = smoothing.powi(bucket.count as i32);
+            let next = average.map_or(mean, |average| keep * average + (1.0 - keep) * mean);
+            average = Some(next);
+            next as F
+        };
+
+        // the values before only set where the average starts, so coarse buckets will do.
+        if let Some(first) = buckets.first().filter(|first| first.start > 0) {
+            self.buckets(0..first.start, self.capacity).iter().for_each(|bucket| { step(bucket); });
+        }
+        buckets.iter().map(step).collect()
+    }
+}
+
+impl Extend<F> for History {
+    fn extend<T: IntoIterator<Item = F>>(&mut self, values: T) {
+        for value in values {
+            self.push(value);
+        }
+    }
+}
+
+// level `k`, made by merging the buckets of level `k - 1` (which still starts at 0) in pairs.
+fn merge_pairs(level: &VecDeque<Bucket>, k: usize) -> VecDeque<Bucket> {
+    let mut coarser: VecDeque<Bucket> = VecDeque::new();
+
+    for bucket in level {
+        match coarser.back_mut() {
+            Some(last) if last.start >> k == bucket.start >> k => last.merge(bucket),
+            _ => coarser.push_back(*bucket),
+        }
+    }
+    coarser
+}
@@ -0,0 +1,222 @@
+
+use super::{ NeuralNet, NNData, Gradients };
+use super::math::F;
+use super::regularization::Regularization;
+use super::clipping::GradientClipping;
+use super::layer::running_average;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use std::iter::zip;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering::Relaxed};
+use std::sync::mpsc::Sender;
+use std::thread::{self, JoinHandle};
+
+// each worker sends its losses on in chunks of this many, rather than one message per batch.
+const LOSSES_PER_SEND: usize = 200;
+
+/// Parameters (and batch norm running statistics) of a `NeuralNet` stored as atomics
+/// (f32 bits in an `AtomicU32`), so any number of threads can read and update them
+/// without a lock.
+///
+/// All accesses are relaxed and updates are a plain load followed by a store, so
+/// concurrent updates to the same weight can be lost. That's the Hogwild trade-off:
+/// gradient updates are small and mostly touch different weights, so the occasional
+/// lost or stale update doesn't stop SGD from converging.
+#[derive(Debug)]
+pub struct SharedWeights {
+    parameters: Vec<Vec<AtomicU32>>,
+    statistics: Vec<Vec<AtomicU32>>,
+    learning_rate: AtomicU32,
+    l1: AtomicU32,
+    l2: AtomicU32,
+    // infinite when there's no limit.
+    clip_value: AtomicU32,
+    clip_norm: AtomicU32,
+}
+
+impl SharedWeights {
+    pub fn from_net(nn: &NeuralNet) -> Self {
+        let share = |values: Vec<&[F]>| -> Vec<Vec<AtomicU32>> {
+            values.iter()
+                .map(|values| values.iter().map(|x| AtomicU32::new(x.to_bits())).collect())
+                .collect()
+        };
+
+        let shared = Self {
+            parameters: share(nn.parameters()),
+            statistics: share(nn.statistics()),
+            learning_rate: AtomicU32::new(nn.learning_rate.to_bits()),
+            l1: AtomicU32::new(nn.regularization.l1.to_bits()),
+            l2: AtomicU32::new(nn.regularization.l2.to_bits()),
+            clip_value: AtomicU32::new(F::INFINITY.to_bits()),
+            clip_norm: AtomicU32::new(F::INFINITY.to_bits()),
+        };
+        shared.set_gradient_clipping(nn.gradient_clipping);
+        shared
+    }
+
+    /// Copy the current shared weights into `nn`, which must have the same structure.
+    pub fn load_into(&self, nn: &mut NeuralNet) {
+        for (shared, values) in zip(&self.parameters, nn.parameters_mut()) {
+            for (shared, x) in zip(shared, values) {
+                *x = F::from_bits(shared.load(Relaxed));
+            }
+        }
+        for (shared, values) in zip(&self.statistics, nn.statistics_mut()) {
+            for (shared, x) in zip(shared, values) {
+                *x = F::from_bits(shared.load(Relaxed));
+            }
+        }
+    }
+
+    pub fn set_learning_rate(&self, rate: F) {
+        self.learning_rate.store(rate.to_bits(), Relaxed);
+    }
+
+    pub fn set_regularization(&self, regularization: Regularization) {
+        self.l1.store(regularization.l1.to_bits(), Relaxed);
+        self.l2.store(regularization.l2.to_bits(), Relaxed);
+    }
+
+    /// The regularization currently applied, which may have changed since the workers started.
+    pub fn regularization(&self) -> Regularization {
+        Regularization {
+            l1: F::from_bits(self.l1.load(Relaxed)),
+            l2: F::from_bits(self.l2.load(Relaxed)),
+        }
+    }
+
+    pub fn set_gradient_clipping(&self, clipping: GradientClipping) {
+        self.clip_value.store(clipping.value.unwrap_or(F::INFINITY).to_bits(), Relaxed);
+        self.clip_norm.store(clipping.norm.unwrap_or(F::INFINITY).to_bits(), Relaxed);
+    }
+
+    fn apply_gradients(&self, gradients: &Gradients) {
+        let learning_rate = F::from_bits(self.learning_rate.load(Relaxed));
+        let regularization = self.regularization();
+        let limit = |bits: &AtomicU32| Some(F::from_bits(bits.load(Relaxed))).filter(|x| x.is_finite());
+        let clipping = GradientClipping {
+            value: limit(&self.clip_value),
+            norm: limit(&self.clip_norm),
+        };
+        let scale = clipping.norm_scale(gradients.parameters());
+
+        for (shared, gradient) in zip(&self.parameters, gradients.parameters()) {
+            for (shared, &g) in zip(shared, gradient) {
+                // skipping untouched weights keeps threads off each other's cache lines,
+                // unless every weight is being pulled towards 0 anyway.
+                if g != 0.0 || !regularization.is_none() {
+                    let x = F::from_bits(shared.load(Relaxed));
+                    shared.store((x - learning_rate * (scale * clipping.clamp(g) + regularization.gradient(x))).to_bits(), Relaxed);
+                }
+            }
+        }
+
+        if let Some(batch_statistics) = gradients.batch_statistics() {
+            for (shared, batch) in zip(&self.statistics, batch_statistics) {
+                for (shared, batch) in zip(shared, batch) {
+                    let running = F::from_bits(shared.load(Relaxed));
+                    shared.store(running_average(running, batch).to_bits(), Relaxed);
+                }
+            }
+        }
+    }
+}
+
+/// Asynchronous (Hogwild) SGD: every worker thread repeatedly copies the shared
+/// weights, computes the gradient of a small random batch and writes its update
+/// straight back, with no synchronisation between workers.
+#[derive(Debug)]
+pub struct HogwildTrainer {
+    shared: Arc<SharedWeights>,
+    stop: Arc<AtomicBool>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl HogwildTrainer {
+    /// Starts `threads` workers training from the current weights of `nn`.
+    ///
+    /// Each worker trains on batches of `batch_size` samples (1 is plain SGD), for
+    /// `max_samples` samples if given, otherwise until `stop` is called. Per-sample
+    /// losses are sent through `losses_tx` in chunks.
+    pub fn start(
+        nn: &NeuralNet,
+        data: Arc<Vec<NNData>>,
+        threads: usize,
+        batch_size: usize,
+        max_samples: Option<usize>,
+        seed: u64,
+        losses_tx: Sender<Vec<F>>,
+    ) -> Self {
+        assert!(batch_size > 0);
+
+        let shared = Arc::new(SharedWeights::from_net(nn));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let mut seeder = StdRng::seed_from_u64(seed);
+
+        let workers = (0..threads)
+            .map(|_| {
+                let shared = Arc::clone(&shared);
+                let stop = Arc::clone(&stop);
+                let data = Arc::clone(&data);
+                let losses_tx = losses_tx.clone();
+                let mut local = nn.clone();
+                let mut rng = StdRng::seed_from_u64(seeder.random());
+
+                thread::spawn(move || {
+                    let mut gradients = local.zero_gradients();
+                    let mut losses = Vec::with_capacity(LOSSES_PER_SEND);
+                    let mut count = 0;
+
+                    while !stop.load(Relaxed) && max_samples.is_none_or(|max| count < max) {
+                        shared.load_into(&mut local);
+
+                        gradients.clear();
+                        let batch: Vec<&NNData> = (0..batch_size)
+                            .map(|_| &data[rng.random_range(0..data.len())])
+                            .collect();
+                        let batch_losses = local.accumulate_gradients(&batch, &mut gradients, &mut rng);
+                        gradients.scale(1.0 / batch_size as F);
+                        losses.extend(batch_losses);
+                        shared.apply_gradients(&gradients);
+
+                        count += batch_size;
+
+                        if losses.len() >= LOSSES_PER_SEND {
+                            // the receiver going away isn't our problem; keep training until stopped.
+                            let _ = losses_tx.send(std::mem::take(&mut losses));
+                        }
+                    }
+
+                    let _ = losses_tx.send(losses);
+                })
+            })
+            .collect();
+
+        Self {
+            shared,
+            stop,
+            workers,
+        }
+    }
+
+    pub fn shared_weights(&self) -> &SharedWeights {
+        &self.shared
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.workers.iter().all(|w| w.is_finished())
+    }
+
+    /// Signal every worker to stop and wait for them.
+    pub fn stop(self) {
+        self.stop.store(true, Relaxed);
+        for worker in self.workers {
+            worker.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use super::math::{ F, Matrix, dot };
+
+use rand::{ Rng, RngCore };
+use rand_distr::{ Normal, Distribution };
+
+use std::iter::zip;
+
+/// How a layer's weights are first drawn, scaled by its fan-in (inputs feeding each output)
+/// and fan-out (outputs each input feeds) so signals neither vanish nor blow up as they pass
+/// through many layers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Initialization {
+    /// Glorot & Bengio: uniform with variance `2 / (fan_in + fan_out)`.
+    XavierUniform,
+    /// Glorot & Bengio: normal with variance `2 / (fan_in + fan_out)`.
+    XavierNormal,
+    /// Kaiming He et al.: uniform with variance `2 / fan_in`; suits ReLU.
+    HeUniform,
+    /// Kaiming He et al.: normal with variance `2 / fan_in`; suits ReLU.
+    HeNormal,
+    /// Normal with variance `1 / fan_in`.
+    LeCunNormal,
+    /// A random matrix with orthonormal rows (or columns, if it has more rows than columns).
+    Orthogonal,
+    Zeros,
+    Constant(F),
+}
+
+impl Initialization {
+    pub const ALL: [Initialization; 8] = [
+        Initialization::XavierUniform,
+        Initialization::XavierNormal,
+        Initialization::HeUniform,
+        Initialization::HeNormal,
+        Initialization::LeCunNormal,
+        Initialization::Orthogonal,
+        Initialization::Zeros,
+        Initialization::Constant(0.01),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Initialization::XavierUniform => "Xavier Uniform",
+            Initialization::XavierNormal => "Xavier Normal",
+            Initialization::HeUniform => "He Uniform",
+            Initialization::HeNormal => "He Normal",
+            Initialization::LeCunNormal => "LeCun Normal",
+            Initialization::Orthogonal => "Orthogonal",
+            Initialization::Zeros => "Zeros",
+            Initialization::Constant(_) => "Constant",
+        }
+    }
+
+    /// Fill `weights`, which has one row per output (channel) and one column per input.
+    pub fn initialize(&self, weights: &mut Matrix, fan_in: usize, fan_out: usize, rng: &mut dyn RngCore) {
+        let (fan_in, fan_out) = (fan_in as F, fan_out as F);
+
+        match self {
+            Initialization::XavierUniform => fill_uniform(weights, (2.0 / (fan_in + fan_out)).sqrt(), rng),
+            Initialization::XavierNormal => fill_normal(weights, (2.0 / (fan_in + fan_out)).sqrt(), rng),
+            Initialization::HeUniform => fill_uniform(weights, (2.0 / fan_in).sqrt(), rng),
+            Initialization::HeNormal => fill_normal(weights, (2.0 / fan_in).sqrt(), rng),
+            Initialization::LeCunNormal => fill_normal(weights, (1.0 / fan_in).sqrt(), rng),
+            Initialization::Orthogonal => fill_orthogonal(weights, rng),
+            Initialization::Zeros => weights.apply_fn(|x| *x = 0.0),
+            Initialization::Constant(value) => weights.apply_fn(|x| *x = *value),
+        }
+    }
+}
+
+// uniform on [-a, a] has variance a²/3.
+fn fill_uniform(weights: &mut Matrix, std: F, rng: &mut dyn RngCore) {
+    let bound = 3.0_f32.sqrt() * std;
+    weights.apply_fn(|x| *x = rng.random_range(-bound..=bound));
+}
+
+fn fill_normal(weights: &mut Matrix, std: F, rng: &mut dyn RngCore) {
+    let normal = Normal::new(0.0, std).unwrap();
+    weights.apply_fn(|x| *x = normal.sample(rng));
+}
+
+// Gram-Schmidt on random normal vectors: there can only be as many orthonormal vectors as
+// their length, so they're the rows of a wide matrix and the columns of a tall one.
+fn fill_orthogonal(weights: &mut Matrix, rng: &mut dyn RngCore) {
+    let (m, n) = (weights.m(), weights.n());
+    let (count, length) = (m.min(n), m.max(n));
+
+    let normal = Normal::new(0.0, 1.0).unwrap();
+    let mut vectors: Vec<Vec<F>> = Vec::with_capacity(count);
+
+    while vectors.len() < count {
+        let mut v: Vec<F> = (0..length).map(|_| normal.sample(rng)).collect();
+        for u in &vectors {
+            let projection = dot(&v, u).unwrap();
+            for (v, u) in zip(&mut v, u) {
+                *v -= projection * u;
+            }
+        }
+
+        let norm = dot(&v, &v).unwrap().sqrt();
+        // a draw (almost) in the span of the others can't be normalised accurately; redraw it.
+        if norm > 1e-3 {
+            vectors.push(v.iter().map(|x| x / norm).collect());
+        }
+    }
+
+    let orthogonal = Matrix::from_rows(vectors);
+    *weights = if m <= n { orthogonal } else { orthogonal.to_transpose() };
+}
@@ -0,0 +1,177 @@
+use super::math::{ F, Matrix };
+use super::init::Initialization;
+use super::autograd::{ Tape, Var };
+
+use rand::RngCore;
+
+use std::any::Any;
+use std::fmt::Debug;
+use std::io::{ self, Write };
+use std::iter::zip;
+use std::ops::AddAssign;
+
+// how much of the old running statistics is kept each time a batch is folded in.
+const MOMENTUM: F = 0.9;
+
+/// One step of a `Sequential` network, working on a batch with one sample per row.
+///
+/// A layer only records its forward pass on a `Tape`, out of the tape's operations; the
+/// gradients of its parameters and input come from the tape. Passing a batch through a
+/// layer never changes it, so one network can be shared by every training thread.
+pub trait Layer: Debug + Send + Sync + DynLayer {
+    /// Short description for the GUI, like `Dense 784 → 160`.
+    fn name(&self) -> String;
+
+    /// How many values a sample has going in and coming out; `None` for layers that keep
+    /// whatever size they're given.
+    fn sizes(&self) -> Option<(usize, usize)> {
+        None
+    }
+
+    /// Records the output for `input` on `tape`. Every parameter goes on the tape with
+    /// `Tape::parameter`, in the same order as `parameters`, and any batch statistics used
+    /// with `Tape::add_batch_statistics`, in the same order as `statistics`.
+    ///
+    /// `rng` is only given while training, for layers that behave differently then
+    /// (dropout draws its masks from it, batch norm uses the batch's statistics).
+    fn forward(&self, tape: &mut Tape, input: Var, rng: Option<&mut dyn RngCore>) -> Var;
+
+    /// Every value learned by gradient descent.
+    fn parameters(&self) -> Vec<&[F]> {
+        Vec::new()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut [F]> {
+        Vec::new()
+    }
+
+    /// Values tracked from the training batches rather than learned, like batch norm's
+    /// running mean and variance. They're moved towards the statistics summed into
+    /// `LayerGradients::statistics` when the gradients are applied.
+    fn statistics(&self) -> Vec<&[F]> {
+        Vec::new()
+    }
+
+    fn statistics_mut(&mut self) -> Vec<&mut [F]> {
+        Vec::new()
+    }
+
+    /// Draw the weights, before training; biases start at 0.
+    fn initialize(&mut self, _initialization: Initialization, _rng: &mut dyn RngCore) {}
+
+    /// Write the layer (starting with its tag) for `model_file`.
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()>;
+}
+
+/// Object-safe helpers every `Layer` gets for free.
+pub trait DynLayer {
+    fn clone_box(&self) -> Box<dyn Layer>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Layer + Clone + 'static> DynLayer for T {
+    fn clone_box(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Clone for Box<dyn Layer> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Gradients of one layer's parameters, in the same layout as `Layer::parameters`, plus
+/// the batch statistics seen while computing them.
+#[derive(Debug, Clone)]
+pub struct LayerGradients {
+    pub parameters: Vec<Vec<F>>,
+    /// Summed statistics of every batch, in the same layout as `Layer::statistics`.
+    pub statistics: Vec<Vec<F>>,
+    /// How many batches have been summed into `statistics`.
+    pub batches: usize,
+}
+
+impl LayerGradients {
+    pub fn zeros(layer: &dyn Layer) -> Self {
+        Self {
+            parameters: layer.parameters().iter().map(|p| vec![0.0; p.len()]).collect(),
+            statistics: layer.statistics().iter().map(|s| vec![0.0; s.len()]).collect(),
+            batches: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for x in self.parameters.iter_mut().chain(&mut self.statistics).flatten() {
+            *x = 0.0;
+        }
+        self.batches = 0;
+    }
+
+    /// Scales the gradients; the batch statistics are averaged separately.
+    pub fn scale(&mut self, scalar: F) {
+        for x in self.parameters.iter_mut().flatten() {
+            *x *= scalar;
+        }
+    }
+
+    /// The averaged statistics of the batches these gradients came from; `None` if there
+    /// weren't any (the layer has no statistics, or only used its running ones).
+    pub fn batch_statistics(&self) -> Option<Vec<Vec<F>>> {
+        if self.batches == 0 {
+            return None;
+        }
+
+        Some(self.statistics.iter()
+            .map(|sums| sums.iter().map(|x| x / self.batches as F).collect())
+            .collect())
+    }
+
+    /// Add a batch's statistics onto the sums.
+    pub fn add_statistics(&mut self, statistics: &[&[F]]) {
+        for (sums, batch) in zip(&mut self.statistics, statistics) {
+            for (sum, x) in zip(sums, batch.iter()) {
+                *sum += x;
+            }
+        }
+        self.batches += 1;
+    }
+}
+
+impl AddAssign<&LayerGradients> for LayerGradients {
+    fn add_assign(&mut self, rhs: &LayerGradients) {
+        let lhs = self.parameters.iter_mut().chain(&mut self.statistics);
+        let rhs_values = rhs.parameters.iter().chain(&rhs.statistics);
+
+        for (lhs, rhs) in zip(lhs, rhs_values) {
+            for (lhs, rhs) in zip(lhs, rhs) {
+                *lhs += rhs;
+            }
+        }
+        self.batches += rhs.batches;
+    }
+}
+
+/// Apply a function to every row (sample) of a batch.
+pub fn map_rows(matrix: &Matrix, f: impl Fn(&[F]) -> Vec<F>) -> Matrix {
+    Matrix::from_rows(matrix.iter_row_slices().map(f).collect())
+}
+
+/// Apply a function to every pair of matching rows of two batches.
+pub fn zip_rows(a: &Matrix, b: &Matrix, f: impl Fn(&[F], &[F]) -> Vec<F>) -> Matrix {
+    Matrix::from_rows(zip(a.iter_row_slices(), b.iter_row_slices()).map(|(a, b)| f(a, b)).collect())
+}
+
+/// Fold one batch statistic into its running average.
+pub fn running_average(running: F, batch: F) -> F {
+    MOMENTUM * running + (1.0 - MOMENTUM) * batch
+}
@@ -0,0 +1,54 @@
+
+use super::math::Matrix;
+use super::autograd::{ Tape, Var };
+
+/// What training minimises, comparing the network's output with the target values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Loss {
+    /// `Σ (a - t)²`
+    SquaredError,
+    /// `-Σ t ln a`; pairs with a softmax output layer.
+    CrossEntropy,
+    /// `-Σ t ln a + (1 - t) ln(1 - a)`, treating every output as its own yes/no
+    /// question; pairs with a sigmoid output layer.
+    BinaryCrossEntropy,
+}
+
+impl Loss {
+    pub const ALL: [Loss; 3] = [
+        Loss::SquaredError,
+        Loss::CrossEntropy,
+        Loss::BinaryCrossEntropy,
+    ];
+
+    /// Records the loss of every output on `tape`, as a matrix the same shape as `output`,
+    /// so its gradient comes from the tape rather than being written out for every loss.
+    pub fn record(&self, tape: &mut Tape, output: Var, target: Var) -> Var {
+        match self {
+            Loss::SquaredError => {
+                let difference = tape.sub(output, target);
+                tape.mul(difference, difference)
+            }
+            Loss::CrossEntropy => {
+                let ln_output = tape.ln(output);
+                let loss = tape.mul(target, ln_output);
+                tape.scale(loss, -1.0)
+            }
+            Loss::BinaryCrossEntropy => {
+                let output_value = tape.value(output);
+                let ones = tape.leaf(Matrix::from_values(vec![1.0; output_value.m() * output_value.n()], output_value.m(), output_value.n()));
+
+                let ln_output = tape.ln(output);
+                let yes = tape.mul(target, ln_output);
+
+                let one_minus_output = tape.sub(ones, output);
+                let one_minus_target = tape.sub(ones, target);
+                let ln_one_minus_output = tape.ln(one_minus_output);
+                let no = tape.mul(one_minus_target, ln_one_minus_output);
+
+                let loss = tape.add(yes, no);
+                tape.scale(loss, -1.0)
+            }
+        }
+    }
+}
@@ -1,9 +1,13 @@
 
 use std::iter::zip;
-use std::ops::{Mul, MulAssign, Add, Sub};
+use std::ops::{Mul, MulAssign, Add, Sub, AddAssign, SubAssign};
 use std::cmp::PartialEq;
 use std::fmt::Debug;
 
+pub mod tensor;
+
+pub use tensor::Tensor;
+
 #[cfg(test)]
 mod tests;
  
@@ -136,6 +140,42 @@ impl Matrix {
         }
     }
 
+    /// Computes `AᵀB` straight from the row-major values, without building the transpose.
+    pub fn tmul(&self, rhs: &Matrix) -> Matrix {
+        if self.m != rhs.m {
+            panic!("Dimension mismatch when trying to multiply transposed matrix!");
+        }
+
+        let mut out = Matrix::new(self.n, rhs.n);
+
+        for (self_row, rhs_row) in zip(self.iter_row_slices(), rhs.iter_row_slices()) {
+            for (j, &a) in self_row.iter().enumerate() {
+                for (out, b) in zip(out.get_mut_row_slice(j), rhs_row) {
+                    *out += a * b;
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Computes `ABᵀ`; every entry is a dot product of two rows, so no transpose is built.
+    pub fn mul_transpose(&self, rhs: &Matrix) -> Matrix {
+        if self.n != rhs.n {
+            panic!("Dimension mismatch when trying to multiply by transposed matrix!");
+        }
+
+        let mut out = Matrix::new(self.m, rhs.m);
+
+        for (i, self_row) in self.iter_row_slices().enumerate() {
+            for (out, rhs_row) in zip(out.get_mut_row_slice(i), rhs.iter_row_slices()) {
+                *out = dot(self_row, rhs_row).unwrap();
+            }
+        }
+
+        out
+    }
+
     /// Create a matrix from raw array/slice/Vec.
     pub fn from_values(input: impl Vector<F>, m: usize, n: usize) -> Self {
         if n * m != input.size() {
@@ -208,7 +248,7 @@ impl Matrix {
     }
 
     pub fn iter_col(&self, j: usize) -> impl Iterator<Item = F> + '_ {
-        MatrixColIter::new(&self, j)
+        MatrixColIter::new(self, j)
     }
 
     pub fn get_row(&self, i: usize) -> Vec<F> {
@@ -225,6 +265,15 @@ impl Matrix {
         &mut self.values[self.n * i..self.n * (i + 1)]
     }
 
+    /// Gives the underlying values (contiguous rows) without copying them.
+    pub fn get_raw_slice(&self) -> &[F] {
+        &self.values[..]
+    }
+
+    pub fn get_mut_raw_slice(&mut self) -> &mut [F] {
+        &mut self.values[..]
+    }
+
     pub fn get_col(&self, j: usize) -> Vec<F> {
         (0..self.m).map(|i| self.values[j + i * self.n]).collect()
     }
@@ -277,10 +326,15 @@ impl Mul<&Matrix> for &Matrix {
             panic!("Dimension mismatch when trying to multiply matrices!");
         }
 
+        // adding scaled rows of rhs walks both matrices in memory order, rather than
+        // collecting every column of rhs for every row of self.
         let mut out = Matrix::new(self.m, rhs.n);
-        for (i, self_row) in self.iter_rows().enumerate() {
-            for (j, rhs_col) in rhs.iter_cols().enumerate() {
-                out.set_value(dot(&self_row, &rhs_col).unwrap(), i, j);
+        for (i, self_row) in self.iter_row_slices().enumerate() {
+            let out_row = out.get_mut_row_slice(i);
+            for (&a, rhs_row) in zip(self_row, rhs.iter_row_slices()) {
+                for (out, b) in zip(out_row.iter_mut(), rhs_row) {
+                    *out += a * b;
+                }
             }
         }
         out
@@ -334,6 +388,30 @@ impl Add<&Matrix> for &Matrix {
     }
 }
 
+impl AddAssign<&Matrix> for Matrix {
+    fn add_assign(&mut self, rhs: &Matrix) {
+        if self.m != rhs.m || self.n != rhs.n {
+            panic!("Dimension mismatch when trying to add matrices!");
+        }
+
+        for (e1, e2) in zip(self.values.iter_mut(), rhs.values.iter()) {
+            *e1 += e2;
+        }
+    }
+}
+
+impl SubAssign<&Matrix> for Matrix {
+    fn sub_assign(&mut self, rhs: &Matrix) {
+        if self.m != rhs.m || self.n != rhs.n {
+            panic!("Dimension mismatch when trying to subtract matrices!");
+        }
+
+        for (e1, e2) in zip(self.values.iter_mut(), rhs.values.iter()) {
+            *e1 -= e2;
+        }
+    }
+}
+
 impl<T: Vector<F>> Mul<&T> for &Matrix {
     type Output = Vec<F>;
 
@@ -374,10 +452,6 @@ impl PartialEq for Matrix {
 
         true
     }
-
-    fn ne(&self, other: &Self) -> bool {
-        !self.eq(other)
-    }
 }
 
 // iterates over the values of a matrix column.
@@ -413,6 +487,11 @@ pub fn sigmoid(x: F) -> F {
     1.0 / (1.0 + (-x).exp())
 }
 
+pub fn sigmoid_derivative(x: F) -> F {
+    let s = sigmoid(x);
+    s * (1.0 - s)
+}
+
 pub fn dot<U, V>(u: &U, v: &V) -> Option<F>
 where V: Vector<F> + ?Sized,
       U: Vector<F> + ?Sized, {
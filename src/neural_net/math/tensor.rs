@@ -0,0 +1,387 @@
+use super::{ F, Matrix, Vector };
+
+use std::iter::{ self, zip };
+use std::ops::{ Add, Div, Mul, Range, Sub };
+use std::sync::Arc;
+
+/// An n-dimensional array, e.g. a batch of images as `[batch, channels, height, width]`.
+///
+/// The values are shared between a tensor and its views: `permute`, `slice`, `select`,
+/// `broadcast_to` and `reshape` (of a contiguous tensor) only change how an index maps onto
+/// the values, through the `strides` (how far apart neighbouring values along each axis
+/// are). Writing to a tensor copies the values first if anything else can see them.
+#[derive(Debug, Clone)]
+pub struct Tensor {
+    values: Arc<Vec<F>>,
+    shape: Vec<usize>,
+    strides: Vec<usize>,
+    offset: usize,
+}
+
+impl Tensor {
+    /// Get a new tensor, all values initialized to 0.
+    pub fn zeros(shape: &[usize]) -> Self {
+        Self::full(shape, 0.0)
+    }
+
+    pub fn full(shape: &[usize], value: F) -> Self {
+        Self::from_values(vec![value; shape.iter().product()], shape)
+    }
+
+    /// `values` in row-major order, i.e. the last axis changes fastest.
+    pub fn from_values(values: impl Vector<F>, shape: &[usize]) -> Self {
+        let values: Vec<F> = values.elements().collect();
+        if values.len() != shape.iter().product::<usize>() {
+            panic!("{} values don't fit a tensor of shape {shape:?}!", values.len());
+        }
+
+        Self {
+            values: Arc::new(values),
+            shape: shape.to_vec(),
+            strides: contiguous_strides(shape),
+            offset: 0,
+        }
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn strides(&self) -> &[usize] {
+        &self.strides
+    }
+
+    /// Number of axes.
+    pub fn ndim(&self) -> usize {
+        self.shape.len()
+    }
+
+    /// Number of values.
+    pub fn len(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the values are laid out in row-major order with nothing in between.
+    pub fn is_contiguous(&self) -> bool {
+        self.strides == contiguous_strides(&self.shape)
+    }
+
+    pub fn get(&self, index: &[usize]) -> F {
+        self.values[self.offset_of(index)]
+    }
+
+    pub fn set(&mut self, index: &[usize], value: F) {
+        // a view may share (or, broadcast, repeat) its values, which mustn't change with it.
+        if Arc::strong_count(&self.values) > 1 || !self.is_contiguous() {
+            *self = Self::from_values(self.to_vec(), &self.shape);
+        }
+
+        let offset = self.offset_of(index);
+        Arc::make_mut(&mut self.values)[offset] = value;
+    }
+
+    fn offset_of(&self, index: &[usize]) -> usize {
+        if index.len() != self.ndim() || zip(index, &self.shape).any(|(i, size)| i >= size) {
+            panic!("index {index:?} is out of bounds for shape {:?}!", self.shape);
+        }
+
+        self.offset + zip(index, &self.strides).map(|(i, stride)| i * stride).sum::<usize>()
+    }
+
+    // where each value is in `values`, in row-major order.
+    fn offsets(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut index = vec![0; self.ndim()];
+        let mut offset = self.offset;
+        let mut remaining = self.len();
+
+        iter::from_fn(move || {
+            if remaining == 0 {
+                return None;
+            }
+            remaining -= 1;
+            let current = offset;
+
+            // count up like an odometer, the last axis first.
+            for axis in (0..self.ndim()).rev() {
+                index[axis] += 1;
+                offset += self.strides[axis];
+                if index[axis] < self.shape[axis] {
+                    break;
+                }
+                offset -= self.strides[axis] * self.shape[axis];
+                index[axis] = 0;
+            }
+
+            Some(current)
+        })
+    }
+
+    /// The values in row-major order.
+    pub fn iter(&self) -> impl Iterator<Item = F> + '_ {
+        self.offsets().map(|offset| self.values[offset])
+    }
+
+    pub fn to_vec(&self) -> Vec<F> {
+        self.iter().collect()
+    }
+
+    /// The same tensor with its values laid out in row-major order; only copies if they aren't.
+    pub fn contiguous(&self) -> Self {
+        if self.is_contiguous() {
+            self.clone()
+        } else {
+            Self::from_values(self.to_vec(), &self.shape)
+        }
+    }
+
+    /// The same values (in row-major order) with a different shape.
+    pub fn reshape(&self, shape: &[usize]) -> Self {
+        if shape.iter().product::<usize>() != self.len() {
+            panic!("can't reshape a tensor of shape {:?} to {shape:?}!", self.shape);
+        }
+
+        Self {
+            shape: shape.to_vec(),
+            strides: contiguous_strides(shape),
+            ..self.contiguous()
+        }
+    }
+
+    /// Reorders the axes: axis `i` of the result is axis `axes[i]` of this tensor.
+    pub fn permute(&self, axes: &[usize]) -> Self {
+        let mut sorted = axes.to_vec();
+        sorted.sort();
+        if sorted != (0..self.ndim()).collect::<Vec<_>>() {
+            panic!("{axes:?} isn't an order of the {} axes!", self.ndim());
+        }
+
+        Self {
+            values: self.values.clone(),
+            shape: axes.iter().map(|&axis| self.shape[axis]).collect(),
+            strides: axes.iter().map(|&axis| self.strides[axis]).collect(),
+            offset: self.offset,
+        }
+    }
+
+    /// A view of part of one axis.
+    pub fn slice(&self, axis: usize, range: Range<usize>) -> Self {
+        if range.start > range.end || range.end > self.shape[axis] {
+            panic!("{range:?} is out of bounds for axis {axis} of shape {:?}!", self.shape);
+        }
+
+        let mut view = self.clone();
+        view.offset += range.start * self.strides[axis];
+        view.shape[axis] = range.len();
+        view
+    }
+
+    /// A view of one index along an axis, without that axis (e.g. one image of a batch).
+    pub fn select(&self, axis: usize, index: usize) -> Self {
+        let mut view = self.slice(axis, index..index + 1);
+        view.shape.remove(axis);
+        view.strides.remove(axis);
+        view
+    }
+
+    /// A view repeating this tensor to fill `shape`, following the usual broadcasting
+    /// rules: axes are matched from the last one, and each must be the same size or 1 (or
+    /// missing), in which case it's repeated.
+    pub fn broadcast_to(&self, shape: &[usize]) -> Self {
+        let cant_broadcast = || panic!("can't broadcast a tensor of shape {:?} to {shape:?}!", self.shape);
+
+        if shape.len() < self.ndim() {
+            cant_broadcast();
+        }
+        let new_axes = shape.len() - self.ndim();
+
+        let mut strides = vec![0; shape.len()];
+        for (axis, (&size, &stride)) in zip(&self.shape, &self.strides).enumerate() {
+            if size == shape[new_axes + axis] {
+                strides[new_axes + axis] = stride;
+            } else if size != 1 {
+                cant_broadcast();
+            }
+        }
+
+        Self {
+            values: self.values.clone(),
+            shape: shape.to_vec(),
+            strides,
+            offset: self.offset,
+        }
+    }
+
+    pub fn map(&self, f: impl FnMut(F) -> F) -> Self {
+        Self::from_values(self.iter().map(f).collect::<Vec<_>>(), &self.shape)
+    }
+
+    /// Combines the values of two tensors pairwise, after broadcasting them to the same shape.
+    pub fn zip_map(&self, other: &Tensor, mut f: impl FnMut(F, F) -> F) -> Self {
+        let shape = broadcast_shape(&self.shape, &other.shape).unwrap_or_else(|| {
+            panic!("Dimension mismatch between tensors of shape {:?} and {:?}!", self.shape, other.shape)
+        });
+
+        let lhs = self.broadcast_to(&shape);
+        let rhs = other.broadcast_to(&shape);
+
+        Self::from_values(zip(lhs.iter(), rhs.iter()).map(|(a, b)| f(a, b)).collect::<Vec<_>>(), &shape)
+    }
+
+    // folds the values along an axis; the axis is kept with size 1, so the result broadcasts
+    // against this tensor.
+    fn reduce_axis(&self, axis: usize, init: F, mut f: impl FnMut(F, F) -> F) -> Self {
+        if axis >= self.ndim() {
+            panic!("axis {axis} is out of bounds for shape {:?}!", self.shape);
+        }
+
+        let mut shape = self.shape.clone();
+        shape[axis] = 1;
+
+        // move the axis to the end, so each fold is over consecutive values.
+        let mut axes: Vec<usize> = (0..self.ndim()).filter(|&a| a != axis).collect();
+        axes.push(axis);
+        let values = self.permute(&axes).to_vec();
+
+        let reduced: Vec<F> = match self.shape[axis] {
+            0 => vec![init; shape.iter().product()],
+            size => values.chunks(size).map(|values| values.iter().copied().fold(init, &mut f)).collect(),
+        };
+
+        Self::from_values(reduced, &shape)
+    }
+
+    /// Sums along an axis, which is kept with size 1.
+    pub fn sum_axis(&self, axis: usize) -> Self {
+        self.reduce_axis(axis, 0.0, |a, b| a + b)
+    }
+
+    /// Means along an axis, which is kept with size 1.
+    pub fn mean_axis(&self, axis: usize) -> Self {
+        let size = self.shape[axis] as F;
+        self.sum_axis(axis).map(|sum| sum / size)
+    }
+
+    /// Maxima along an axis, which is kept with size 1.
+    pub fn max_axis(&self, axis: usize) -> Self {
+        self.reduce_axis(axis, F::NEG_INFINITY, F::max)
+    }
+
+    /// Minima along an axis, which is kept with size 1.
+    pub fn min_axis(&self, axis: usize) -> Self {
+        self.reduce_axis(axis, F::INFINITY, F::min)
+    }
+
+    pub fn sum(&self) -> F {
+        self.iter().sum()
+    }
+
+    pub fn mean(&self) -> F {
+        self.sum() / self.len() as F
+    }
+
+    /// The tensor as a matrix; it must have exactly 2 axes.
+    pub fn to_matrix(&self) -> Matrix {
+        if self.ndim() != 2 {
+            panic!("a tensor of shape {:?} isn't a matrix!", self.shape);
+        }
+
+        Matrix::from_values(self.to_vec(), self.shape[0], self.shape[1])
+    }
+}
+
+impl From<&Matrix> for Tensor {
+    fn from(matrix: &Matrix) -> Self {
+        Self::from_values(matrix.get_raw_values(), &[matrix.m(), matrix.n()])
+    }
+}
+
+impl Vector<F> for Tensor {
+    fn scale(&self, scalar: F) -> impl Vector<F> {
+        self * scalar
+    }
+
+    fn size(&self) -> usize {
+        self.len()
+    }
+
+    fn elements(&self) -> impl Iterator<Item = F> {
+        self.iter()
+    }
+}
+
+impl PartialEq for Tensor {
+    fn eq(&self, other: &Self) -> bool {
+        self.shape == other.shape && self.iter().eq(other.iter())
+    }
+}
+
+impl Add<&Tensor> for &Tensor {
+    type Output = Tensor;
+
+    fn add(self, rhs: &Tensor) -> Tensor {
+        self.zip_map(rhs, |a, b| a + b)
+    }
+}
+
+impl Sub<&Tensor> for &Tensor {
+    type Output = Tensor;
+
+    fn sub(self, rhs: &Tensor) -> Tensor {
+        self.zip_map(rhs, |a, b| a - b)
+    }
+}
+
+/// Element-wise (Hadamard) product.
+impl Mul<&Tensor> for &Tensor {
+    type Output = Tensor;
+
+    fn mul(self, rhs: &Tensor) -> Tensor {
+        self.zip_map(rhs, |a, b| a * b)
+    }
+}
+
+impl Div<&Tensor> for &Tensor {
+    type Output = Tensor;
+
+    fn div(self, rhs: &Tensor) -> Tensor {
+        self.zip_map(rhs, |a, b| a / b)
+    }
+}
+
+impl Mul<F> for &Tensor {
+    type Output = Tensor;
+
+    fn mul(self, rhs: F) -> Tensor {
+        self.map(|x| x * rhs)
+    }
+}
+
+fn contiguous_strides(shape: &[usize]) -> Vec<usize> {
+    let mut strides = vec![1; shape.len()];
+    for axis in (0..shape.len().saturating_sub(1)).rev() {
+        strides[axis] = strides[axis + 1] * shape[axis + 1];
+    }
+    strides
+}
+
+// the shape two tensors broadcast to, if they can.
+fn broadcast_shape(a: &[usize], b: &[usize]) -> Option<Vec<usize>> {
+    let ndim = a.len().max(b.len());
+    // sizes from the last axis, with missing axes as 1.
+    let size = |shape: &[usize], i: usize| if i < shape.len() { shape[shape.len() - 1 - i] } else { 1 };
+
+    let mut shape: Vec<usize> = (0..ndim)
+        .map(|i| match (size(a, i), size(b, i)) {
+            (a, b) if a == b || b == 1 => Some(a),
+            (1, b) => Some(b),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+
+    shape.reverse();
+    Some(shape)
+}
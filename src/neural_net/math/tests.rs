@@ -24,7 +24,7 @@ fn test_dot_product() {
     let dots: [F;16] = [
         4.02,   20.3,   6.5213,     3.278,
         20.3,   105.0,  35.426,     17.81,
-        6.5213, 35.426, 34.045369,  18.25639,
+        6.5213, 35.426, 34.045_37,  18.25639,
         3.278,  17.81,  18.25639,   9.8134,
     ];
 
@@ -36,7 +36,7 @@ fn test_dot_product() {
         }
     }
 
-    assert!(dot(&[1.0,2.0], &[1.0,2.0,3.0]) == None);
+    assert!(dot(&[1.0,2.0], &[1.0,2.0,3.0]).is_none());
 
     // can we dot product array slices?
     assert!((dot(&vecs[0][0..2], &vecs[1][0..2]).unwrap() - 0.3).abs() < tolerance);
@@ -230,6 +230,40 @@ fn test_matrix_transpose() {
     assert_eq!(mat_t.get_val(2, 1).unwrap(), 6.0);
 }
 
+#[test]
+fn test_matrix_tmul() {
+    for (m, n, p) in [(1, 1, 1), (2, 3, 4), (4, 2, 3), (3, 3, 3)] {
+        let a = generate_matrix(m, n, -2.0);
+        let b = generate_matrix(m, p, 0.5);
+
+        let product = a.tmul(&b);
+        let expected = &a.to_transpose() * &b;
+
+        assert_eq!(product.m, n);
+        assert_eq!(product.n, p);
+        for (x, y) in zip(product.get_raw_values(), expected.get_raw_values()) {
+            assert!(compare_equal_f(x, y));
+        }
+    }
+}
+
+#[test]
+fn test_matrix_mul_transpose() {
+    for (m, n, p) in [(1, 1, 1), (2, 3, 4), (4, 2, 3), (3, 3, 3)] {
+        let a = generate_matrix(m, n, -2.0);
+        let b = generate_matrix(p, n, 0.5);
+
+        let product = a.mul_transpose(&b);
+        let expected = &a * &b.to_transpose();
+
+        assert_eq!(product.m, m);
+        assert_eq!(product.n, p);
+        for (x, y) in zip(product.get_raw_values(), expected.get_raw_values()) {
+            assert!(compare_equal_f(x, y));
+        }
+    }
+}
+
 #[test]
 fn test_matrix_new_identity() {
     for size in [1, 2, 3, 5] {
@@ -278,8 +312,8 @@ fn test_matrix_multiplication() {
     }
 
     // Test dimension mismatch
-    let bigger = Matrix::new(3, 3);
-    // let _ = &a * &bigger; // uncomment to see panic
+    let _bigger = Matrix::new(3, 3);
+    // let _ = &a * &_bigger; // uncomment to see panic
 }
 
 #[test]
@@ -435,3 +469,98 @@ fn test_matrix_addition_substraction_scalar_multiplication() {
 
     assert!(out == expected);
 }
+
+// 0, 1, 2, ... in a tensor of the given shape.
+fn generate_tensor(shape: &[usize]) -> Tensor {
+    Tensor::from_values((0..shape.iter().product()).map(|x| x as F).collect::<Vec<_>>(), shape)
+}
+
+#[test]
+fn test_tensor_views() {
+    let t = generate_tensor(&[2, 3, 4]);
+    assert_eq!(t.strides(), [12, 4, 1]);
+    assert_eq!(t.get(&[1, 2, 3]), 23.0);
+
+    // reshaping keeps the row-major order.
+    let reshaped = t.reshape(&[6, 4]);
+    assert_eq!(reshaped.get(&[5, 1]), 21.0);
+    assert_eq!(reshaped.to_vec(), t.to_vec());
+
+    let permuted = t.permute(&[2, 0, 1]);
+    assert_eq!(permuted.shape(), [4, 2, 3]);
+    assert!(!permuted.is_contiguous());
+    assert_eq!(permuted.get(&[3, 1, 2]), t.get(&[1, 2, 3]));
+    // reshaping a permuted tensor has to copy it into its new order.
+    assert_eq!(permuted.reshape(&[24]).to_vec()[..4], [0.0, 4.0, 8.0, 12.0]);
+
+    let sliced = t.slice(1, 1..3);
+    assert_eq!(sliced.shape(), [2, 2, 4]);
+    assert_eq!(sliced.get(&[1, 0, 2]), t.get(&[1, 1, 2]));
+
+    let selected = t.select(0, 1).select(1, 3);
+    assert_eq!(selected.shape(), [3]);
+    assert_eq!(selected.to_vec(), [15.0, 19.0, 23.0]);
+
+    // writing to a view doesn't change what it's a view of.
+    let mut view = t.slice(0, 1..2);
+    view.set(&[0, 0, 0], -1.0);
+    assert_eq!(view.get(&[0, 0, 0]), -1.0);
+    assert_eq!(t.get(&[1, 0, 0]), 12.0);
+}
+
+#[test]
+fn test_tensor_broadcasting() {
+    let t = generate_tensor(&[2, 3]);
+    let row = Tensor::from_values(vec![10.0, 20.0, 30.0], &[3]);
+    let column = Tensor::from_values(vec![1.0, 2.0], &[2, 1]);
+
+    assert_eq!((&t + &row).to_vec(), [10.0, 21.0, 32.0, 13.0, 24.0, 35.0]);
+    assert_eq!((&t * &column).to_vec(), [0.0, 1.0, 2.0, 6.0, 8.0, 10.0]);
+    assert_eq!((&row - &column).shape(), [2, 3]);
+    assert_eq!((&row / &column).to_vec(), [10.0, 20.0, 30.0, 5.0, 10.0, 15.0]);
+    assert_eq!((&t * 2.0).to_vec(), [0.0, 2.0, 4.0, 6.0, 8.0, 10.0]);
+
+    let broadcast = column.broadcast_to(&[4, 2, 3]);
+    assert_eq!(broadcast.strides(), [0, 1, 0]);
+    assert_eq!(broadcast.sum(), 36.0);
+}
+
+#[test]
+#[should_panic]
+fn test_tensor_broadcasting_mismatch() {
+    let _ = &generate_tensor(&[2, 3]) + &generate_tensor(&[2]);
+}
+
+#[test]
+fn test_tensor_reductions() {
+    let t = generate_tensor(&[2, 3, 4]);
+
+    let sum = t.sum_axis(1);
+    assert_eq!(sum.shape(), [2, 1, 4]);
+    assert_eq!(sum.to_vec(), [12.0, 15.0, 18.0, 21.0, 48.0, 51.0, 54.0, 57.0]);
+
+    assert_eq!(t.mean_axis(0).to_vec(), (6..18).map(|x| x as F).collect::<Vec<_>>());
+    assert_eq!(t.max_axis(2).to_vec(), [3.0, 7.0, 11.0, 15.0, 19.0, 23.0]);
+    assert_eq!(t.min_axis(2).reshape(&[6]), &generate_tensor(&[6]) * 4.0);
+    assert!(compare_equal_f(t.mean(), 11.5));
+
+    // the reduced axis is kept, so the result broadcasts back against the tensor.
+    let centered = &t - &t.mean_axis(2);
+    assert!(centered.sum_axis(2).iter().all(|x| compare_equal_f(x, 0.0)));
+}
+
+#[test]
+fn test_tensor_matrix_and_vector_interop() {
+    let matrix = generate_matrix(3, 2, 1.0);
+    let t = Tensor::from(&matrix);
+    assert_eq!(t.shape(), [3, 2]);
+    assert!(t.to_matrix() == matrix);
+
+    // a transposed view is the transposed matrix.
+    assert!(t.permute(&[1, 0]).to_matrix() == matrix.to_transpose());
+
+    // tensors work anywhere a Vector does.
+    assert_eq!(dot(&t, &vec![1.0; 6]), Some(21.0));
+    assert_eq!(&matrix * &t.select(0, 0), vec![5.0, 11.0, 17.0]);
+    assert_eq!(Tensor::from_values(vec![1.0, 2.0, 3.0], &[3]).scale(2.0).elements().collect::<Vec<_>>(), [2.0, 4.0, 6.0]);
+}
@@ -0,0 +1,257 @@
+use super::NeuralNet;
+use super::math::F;
+use super::sequential::Sequential;
+use super::dense::Dense;
+use super::activation::Activation;
+use super::dropout::Dropout;
+use super::batch_norm::BatchNorm;
+use super::conv::Conv2D;
+use super::pooling::{ AvgPool2D, MaxPool2D };
+use super::loss::Loss;
+use super::regularization::Regularization;
+use super::clipping::GradientClipping;
+use super::optimizer::Optimizer;
+use super::init::Initialization;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use std::fs::File;
+use std::io::{ self, BufReader, BufWriter, ErrorKind, Read, Write };
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"NNFS";
+// what a model for the app has to take in (a 28×28 image) and give out (a score per digit).
+const IMAGE_INPUTS: usize = 28 * 28;
+const DIGITS: usize = 10;
+const VERSION: u32 = 4;
+// files from before the seed was recorded.
+const UNSEEDED_VERSION: u32 = 2;
+// files from before the optimizer, clipping and initialization were recorded.
+const SEEDED_VERSION: u32 = 3;
+
+// far more values than any layer here has; a size over this means the file is corrupt, and
+// reading it would try to allocate that much.
+const MAX_VALUES: usize = 1 << 28;
+
+// the tag every layer's data starts with.
+pub const DENSE: u8 = 0;
+pub const ACTIVATION: u8 = 1;
+pub const DROPOUT: u8 = 2;
+pub const BATCH_NORM: u8 = 3;
+pub const CONV_2D: u8 = 4;
+pub const MAX_POOL_2D: u8 = 5;
+pub const AVG_POOL_2D: u8 = 6;
+
+// File layout (all numbers little-endian):
+//
+//   "NNFS", version: u32, layer count: u32
+//   every layer, written by `Layer::write_to`: its tag (u8), then
+//     dense: m: u32, n: u32, weights: m·n f32 (row-major)
+//     activation: u8
+//     dropout: probability: f32
+//     batch norm: features: u32, gamma, beta, running mean, running variance: f32 each
+//     conv 2d: input channels, height, width, output channels, kernel size, stride, padding: u32,
+//              weights: f32 (one row per output channel), bias: f32 per output channel
+//     max / avg pool 2d: input channels, height, width, window size, stride: u32
+//   loss: u8, learning rate: f32, l1: f32, l2: f32
+//   seed: u64 (since version 3)
+//   since version 4:
+//     optimizer: u8, then momentum: beta: f32, adam: beta1, beta2, epsilon: f32
+//     gradient clipping: value, norm: f32 (infinite for no limit)
+//     initialization: u8, then for a constant its value: f32
+//
+// Activations, losses, optimizers and initializations are stored as their index in
+// `Activation::ALL`, `Loss::ALL`, `Optimizer::ALL` / `Initialization::ALL`.
+
+impl NeuralNet {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Read a model for the app from `path`; unlike `read_from`, this rejects nets that
+    /// don't take a 28×28 image and give 10 outputs.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let nn = Self::read_from(&mut BufReader::new(File::open(path)?))?;
+
+        let sizes: Vec<(usize, usize)> = nn.layers.layers().iter().filter_map(|layer| layer.sizes()).collect();
+        let (inputs, outputs) = (sizes[0].0, sizes[sizes.len() - 1].1);
+        if (inputs, outputs) != (IMAGE_INPUTS, DIGITS) {
+            return Err(invalid_data(&format!(
+                "neural net file has {inputs} inputs and {outputs} outputs, not {IMAGE_INPUTS} and {DIGITS}")));
+        }
+        Ok(nn)
+    }
+
+    /// Write the layers (with their parameters and statistics) and training settings.
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        write_u32(writer, VERSION)?;
+        write_u32(writer, self.layers.layers().len() as u32)?;
+
+        for layer in self.layers.layers() {
+            layer.write_to(writer)?;
+        }
+
+        writer.write_all(&[index_of(&Loss::ALL, self.loss)])?;
+        write_floats(writer, &[self.learning_rate, self.regularization.l1, self.regularization.l2])?;
+        writer.write_all(&self.seed.to_le_bytes())?;
+
+        writer.write_all(&[index_by_name(&Optimizer::ALL, self.optimizer, Optimizer::name)])?;
+        match self.optimizer {
+            Optimizer::Sgd => {}
+            Optimizer::Momentum { beta } => write_floats(writer, &[beta])?,
+            Optimizer::Adam { beta1, beta2, epsilon } => write_floats(writer, &[beta1, beta2, epsilon])?,
+        }
+
+        let limit = |limit: Option<F>| limit.unwrap_or(F::INFINITY);
+        write_floats(writer, &[limit(self.gradient_clipping.value), limit(self.gradient_clipping.norm)])?;
+
+        writer.write_all(&[index_by_name(&Initialization::ALL, self.initialization, Initialization::name)])?;
+        if let Initialization::Constant(value) = self.initialization {
+            write_floats(writer, &[value])?;
+        }
+        Ok(())
+    }
+
+    /// Read a network written by `write_to`. It starts out in training mode, with its rng
+    /// back at the start of its seed.
+    pub fn read_from(reader: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a neural net file"));
+        }
+
+        let version = read_u32(reader)?;
+        if ![VERSION, SEEDED_VERSION, UNSEEDED_VERSION].contains(&version) {
+            return Err(invalid_data(&format!("unsupported neural net file version {version}")));
+        }
+
+        let num_layers = read_u32(reader)? as usize;
+        if num_layers == 0 {
+            return Err(invalid_data("neural net file has no layers"));
+        }
+
+        let mut layers = Sequential::new();
+        for _ in 0..num_layers {
+            match read_u8(reader)? {
+                DENSE => layers.push(Dense::read_from(reader)?),
+                ACTIVATION => layers.push(Activation::read_from(reader)?),
+                DROPOUT => layers.push(Dropout::read_from(reader)?),
+                BATCH_NORM => layers.push(BatchNorm::read_from(reader)?),
+                CONV_2D => layers.push(Conv2D::read_from(reader)?),
+                MAX_POOL_2D => layers.push(MaxPool2D::read_from(reader)?),
+                AVG_POOL_2D => layers.push(AvgPool2D::read_from(reader)?),
+                tag => return Err(invalid_data(&format!("unknown layer type {tag} in neural net file"))),
+            }
+        }
+
+        // layers that keep the size of their input don't get a say.
+        let sizes: Vec<(usize, usize)> = layers.layers().iter().filter_map(|layer| layer.sizes()).collect();
+        if sizes.windows(2).any(|pair| pair[0].1 != pair[1].0) {
+            return Err(invalid_data("layer sizes in neural net file don't match up"));
+        }
+        if !layers.layers().iter().any(|layer| layer.as_any().is::<Dense>()) {
+            return Err(invalid_data("neural net file has no dense layers"));
+        }
+
+        let mut nn = NeuralNet::from_layers(layers);
+
+        nn.loss = *from_index(&Loss::ALL, read_u8(reader)?)?;
+
+        let settings = read_floats(reader, 3)?;
+        nn.learning_rate = settings[0];
+        nn.regularization = Regularization { l1: settings[1], l2: settings[2] };
+
+        if version >= SEEDED_VERSION {
+            nn.seed = read_u64(reader)?;
+            nn.rng = StdRng::seed_from_u64(nn.seed);
+        }
+
+        if version >= VERSION {
+            nn.optimizer = match *from_index(&Optimizer::ALL, read_u8(reader)?)? {
+                Optimizer::Sgd => Optimizer::Sgd,
+                Optimizer::Momentum { .. } => Optimizer::Momentum { beta: read_floats(reader, 1)?[0] },
+                Optimizer::Adam { .. } => {
+                    let betas = read_floats(reader, 3)?;
+                    Optimizer::Adam { beta1: betas[0], beta2: betas[1], epsilon: betas[2] }
+                }
+            };
+
+            let limits = read_floats(reader, 2)?;
+            let limit = |limit: F| Some(limit).filter(|limit| limit.is_finite());
+            nn.gradient_clipping = GradientClipping { value: limit(limits[0]), norm: limit(limits[1]) };
+
+            nn.initialization = match *from_index(&Initialization::ALL, read_u8(reader)?)? {
+                Initialization::Constant(_) => Initialization::Constant(read_floats(reader, 1)?[0]),
+                initialization => initialization,
+            };
+        }
+
+        Ok(nn)
+    }
+}
+
+pub fn index_of<T: PartialEq>(all: &[T], value: T) -> u8 {
+    all.iter().position(|x| *x == value).unwrap() as u8
+}
+
+// for values like `Optimizer::Adam`, whose settings needn't match the one in `all`.
+fn index_by_name<T>(all: &[T], value: T, name: fn(&T) -> &'static str) -> u8 {
+    all.iter().position(|x| name(x) == name(&value)).unwrap() as u8
+}
+
+pub fn from_index<T>(all: &[T], index: u8) -> io::Result<&T> {
+    all.get(index as usize).ok_or_else(|| invalid_data("unknown activation, loss, optimizer or initialization in neural net file"))
+}
+
+pub fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+/// `m·n`, as the number of values in a layer read from a file, if that's believable.
+pub fn checked_size(m: usize, n: usize) -> io::Result<usize> {
+    m.checked_mul(n)
+        .filter(|&size| size <= MAX_VALUES)
+        .ok_or_else(|| invalid_data("layer in neural net file is too large"))
+}
+
+pub fn write_u32(writer: &mut (impl Write + ?Sized), value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+pub fn write_floats(writer: &mut (impl Write + ?Sized), values: &[F]) -> io::Result<()> {
+    for value in values {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn read_u8(reader: &mut (impl Read + ?Sized)) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+pub fn read_u32(reader: &mut (impl Read + ?Sized)) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub fn read_u64(reader: &mut (impl Read + ?Sized)) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub fn read_floats(reader: &mut (impl Read + ?Sized), count: usize) -> io::Result<Vec<F>> {
+    let mut bytes = vec![0; count * size_of::<F>()];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes.chunks_exact(size_of::<F>())
+        .map(|chunk| F::from_le_bytes(chunk.try_into().unwrap()))
+        .collect())
+}
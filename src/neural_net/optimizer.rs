@@ -0,0 +1,100 @@
+use super::math::F;
+
+/// How a gradient descent step turns the gradient into a change of the weights.
+///
+/// Hogwild workers always take plain SGD steps; they share nothing but the weights.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Optimizer {
+    /// `w -= learning_rate * g`
+    Sgd,
+    /// Steps along a running sum of the gradients, `v = beta * v + g`, so directions the
+    /// gradient keeps pointing in speed up.
+    Momentum { beta: F },
+    /// Kingma & Ba: every weight's step is scaled by a running average of its squared gradient,
+    /// so each takes steps of about the learning rate, whatever the size of its gradient.
+    Adam { beta1: F, beta2: F, epsilon: F },
+}
+
+/// The running averages an `Optimizer` keeps between steps, one per parameter.
+#[derive(Debug, Clone, Default)]
+pub struct OptimizerState {
+    steps: i32,
+    first_moments: Vec<Vec<F>>,
+    second_moments: Vec<Vec<F>>,
+}
+
+impl Optimizer {
+    pub const ALL: [Optimizer; 3] = [
+        Optimizer::Sgd,
+        Optimizer::Momentum { beta: 0.9 },
+        Optimizer::Adam { beta1: 0.9, beta2: 0.999, epsilon: 1e-8 },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Optimizer::Sgd => "SGD",
+            Optimizer::Momentum { .. } => "Momentum",
+            Optimizer::Adam { .. } => "Adam",
+        }
+    }
+
+    /// One step on `parameters`; `gradient(slice, index, w)` is the gradient of the value at
+    /// `index` in the `slice`th parameter slice, whose value is `w`.
+    pub fn step(
+        &self,
+        state: &mut OptimizerState,
+        learning_rate: F,
+        parameters: Vec<&mut [F]>,
+        gradient: impl Fn(usize, usize, F) -> F,
+    ) {
+        state.fit(&parameters);
+        state.steps = state.steps.saturating_add(1);
+
+        for (slice, values) in parameters.into_iter().enumerate() {
+            for (index, w) in values.iter_mut().enumerate() {
+                let g = gradient(slice, index, *w);
+
+                match *self {
+                    Optimizer::Sgd => *w -= learning_rate * g,
+                    Optimizer::Momentum { beta } => {
+                        let velocity = &mut state.first_moments[slice][index];
+                        *velocity = beta * *velocity + g;
+                        *w -= learning_rate * *velocity;
+                    }
+                    Optimizer::Adam { beta1, beta2, epsilon } => {
+                        let m = &mut state.first_moments[slice][index];
+                        *m = beta1 * *m + (1.0 - beta1) * g;
+                        let v = &mut state.second_moments[slice][index];
+                        *v = beta2 * *v + (1.0 - beta2) * g * g;
+
+                        // both averages start at 0, which biases them towards 0 for the first steps.
+                        let m = *m / (1.0 - beta1.powi(state.steps));
+                        let v = *v / (1.0 - beta2.powi(state.steps));
+                        *w -= learning_rate * m / (v.sqrt() + epsilon);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl OptimizerState {
+    // starts over if the parameters aren't the ones the averages were kept for.
+    fn fit(&mut self, parameters: &[&mut [F]]) {
+        let fits = self.first_moments.len() == parameters.len()
+            && self.first_moments.iter().zip(parameters).all(|(moments, values)| moments.len() == values.len());
+
+        if !fits {
+            *self = Self {
+                steps: 0,
+                first_moments: parameters.iter().map(|values| vec![0.0; values.len()]).collect(),
+                second_moments: parameters.iter().map(|values| vec![0.0; values.len()]).collect(),
+            };
+        }
+    }
+
+    /// Forget the running averages, as when the weights are drawn again.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
@@ -0,0 +1,171 @@
+
+use super::{ NeuralNet, NNData, Gradients };
+use super::math::F;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use std::iter::zip;
+use std::panic::{ self, AssertUnwindSafe };
+use std::sync::Arc;
+use std::sync::mpsc::{ self, Receiver, Sender };
+use std::thread::{ self, JoinHandle };
+
+/// Data-parallel mini-batch training.
+///
+/// Each batch is split into one contiguous chunk per worker thread (as near the same size
+/// as can be); every worker accumulates the gradients of its chunk against the same
+/// (read-only) network, and the partial gradients are then summed in chunk order before
+/// a single weight update. Because the chunking and the summation order only depend on
+/// the batch and the thread count, two trainers with the same seed and thread count
+/// produce exactly the same weights.
+///
+/// The worker threads are started with the trainer and wait for chunks between batches;
+/// dropping the trainer ends them.
+///
+/// Batch norm layers normalize each chunk with its own statistics, so they see
+/// batches of about `batch_size / threads` samples. A single sample can't be normalized,
+/// so with batch norm, chunks never get fewer than 2 samples: small batches are split
+/// over fewer threads instead.
+#[derive(Debug)]
+pub struct ParallelTrainer {
+    batch_size: usize,
+    rng: StdRng,
+    workers: Vec<Worker>,
+}
+
+// a thread computing the gradients of whatever chunks it's sent.
+#[derive(Debug)]
+struct Worker {
+    jobs: Sender<Job>,
+    // one channel per worker, so the partial gradients can be summed in chunk order.
+    results: Receiver<thread::Result<(Gradients, Vec<F>)>>,
+    thread: JoinHandle<()>,
+}
+
+// one chunk of a batch.
+struct Job {
+    nn: Arc<NeuralNet>,
+    data: Arc<Vec<NNData>>,
+    chunk: Vec<usize>,
+    seed: u64,
+}
+
+impl ParallelTrainer {
+    pub fn new(threads: usize, batch_size: usize, seed: u64) -> Self {
+        assert!(threads > 0 && batch_size > 0);
+
+        Self {
+            batch_size,
+            rng: StdRng::seed_from_u64(seed),
+            workers: (0..threads).map(|_| Worker::start()).collect(),
+        }
+    }
+
+    /// Pick the indices of the next mini-batch (with replacement, like the single-sample loop).
+    pub fn next_batch(&mut self, data_len: usize) -> Vec<usize> {
+        (0..self.batch_size).map(|_| self.rng.random_range(0..data_len)).collect()
+    }
+
+    /// Averaged gradients over `batch` (indices into `data`), plus the loss of each sample
+    /// (without the regularization penalty).
+    ///
+    /// The workers have let go of `nn` again by the time this returns, so `Arc::make_mut`
+    /// can update it without a copy.
+    pub fn compute_gradients(&mut self, nn: &Arc<NeuralNet>, data: &Arc<Vec<NNData>>, batch: &[usize]) -> (Gradients, Vec<F>) {
+        let threads = self.workers.len();
+        let has_batch_norm = !nn.statistics().is_empty();
+        let chunks = if has_batch_norm { threads.min(batch.len() / 2).max(1) } else { threads.min(batch.len()) };
+        let chunks = split(batch, chunks);
+
+        for (worker, chunk) in zip(&self.workers, &chunks) {
+            let job = Job {
+                nn: Arc::clone(nn),
+                data: Arc::clone(data),
+                chunk: chunk.to_vec(),
+                // each chunk gets its own rng (for dropout), seeded in chunk order.
+                seed: self.rng.random(),
+            };
+            worker.jobs.send(job).expect("parallel training worker exited");
+        }
+
+        // waiting on the workers in chunk order keeps the reduction deterministic.
+        let mut partials = self.workers[..chunks.len()].iter().map(|worker| {
+            match worker.results.recv().expect("parallel training worker exited") {
+                Ok(partial) => partial,
+                Err(panic) => panic::resume_unwind(panic),
+            }
+        });
+        let (mut gradients, mut losses) = partials.next().unwrap();
+
+        for (partial_gradients, partial_losses) in partials {
+            gradients += &partial_gradients;
+            losses.extend(partial_losses);
+        }
+
+        gradients.scale(1.0 / batch.len() as F);
+
+        (gradients, losses)
+    }
+}
+
+impl Drop for ParallelTrainer {
+    fn drop(&mut self) {
+        for Worker { jobs, thread, .. } in self.workers.drain(..) {
+            // closing its channel ends the worker's loop.
+            drop(jobs);
+            // a panic has already been passed on by `compute_gradients`.
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Worker {
+    fn start() -> Self {
+        let (jobs, jobs_rx) = mpsc::channel::<Job>();
+        let (results_tx, results) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            for job in jobs_rx {
+                // a panic is passed on to the trainer, like one in its own thread would be.
+                let partial = panic::catch_unwind(AssertUnwindSafe(|| job.run()));
+                // the net is let go of before the trainer hears back, so it can change it.
+                drop(job);
+
+                if results_tx.send(partial).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            jobs,
+            results,
+            thread,
+        }
+    }
+}
+
+impl Job {
+    fn run(&self) -> (Gradients, Vec<F>) {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut gradients = self.nn.zero_gradients();
+        let data_points: Vec<&NNData> = self.chunk.iter().map(|&i| &self.data[i]).collect();
+        let losses = self.nn.accumulate_gradients(&data_points, &mut gradients, &mut rng);
+        (gradients, losses)
+    }
+}
+
+// `parts` contiguous slices of `values`, whose lengths differ by at most 1.
+fn split(values: &[usize], parts: usize) -> Vec<&[usize]> {
+    let (size, longer) = (values.len() / parts, values.len() % parts);
+    let mut rest = values;
+
+    (0..parts)
+        .map(|part| {
+            let (chunk, after) = rest.split_at(size + usize::from(part < longer));
+            rest = after;
+            chunk
+        })
+        .collect()
+}
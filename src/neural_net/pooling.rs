@@ -0,0 +1,155 @@
+use super::math::F;
+use super::layer::Layer;
+use super::conv::Shape;
+use super::autograd::{ Tape, Var };
+use super::model_file::{ self, read_u32, write_u32 };
+
+use rand::RngCore;
+
+use std::io::{ self, Read, Write };
+
+/// Replaces every `size`×`size` window (moved `stride` pixels at a time) of each channel
+/// with its largest value.
+#[derive(Debug, Clone)]
+pub struct MaxPool2D {
+    input: Shape,
+    size: usize,
+    stride: usize,
+}
+
+/// Replaces every `size`×`size` window (moved `stride` pixels at a time) of each channel
+/// with its mean.
+#[derive(Debug, Clone)]
+pub struct AvgPool2D {
+    input: Shape,
+    size: usize,
+    stride: usize,
+}
+
+impl MaxPool2D {
+    pub fn new(input: Shape, size: usize, stride: usize) -> Self {
+        assert!(stride > 0 && size > 0 && size <= input.height.min(input.width));
+        Self {
+            input,
+            size,
+            stride,
+        }
+    }
+
+    pub fn output_shape(&self) -> Shape {
+        output_shape(self.input, self.size, self.stride)
+    }
+
+    pub(super) fn read_from(reader: &mut dyn Read) -> io::Result<Self> {
+        let (input, size, stride) = read_pooling(reader)?;
+        Ok(Self::new(input, size, stride))
+    }
+}
+
+impl AvgPool2D {
+    pub fn new(input: Shape, size: usize, stride: usize) -> Self {
+        assert!(stride > 0 && size > 0 && size <= input.height.min(input.width));
+        Self {
+            input,
+            size,
+            stride,
+        }
+    }
+
+    pub fn output_shape(&self) -> Shape {
+        output_shape(self.input, self.size, self.stride)
+    }
+
+    pub(super) fn read_from(reader: &mut dyn Read) -> io::Result<Self> {
+        let (input, size, stride) = read_pooling(reader)?;
+        Ok(Self::new(input, size, stride))
+    }
+}
+
+impl Layer for MaxPool2D {
+    fn name(&self) -> String {
+        format!("MaxPool2D {s}×{s} {} → {}", self.input, self.output_shape(), s = self.size)
+    }
+
+    fn sizes(&self) -> Option<(usize, usize)> {
+        Some((self.input.size(), self.output_shape().size()))
+    }
+
+    fn forward(&self, tape: &mut Tape, input: Var, _rng: Option<&mut dyn RngCore>) -> Var {
+        let batch_size = tape.value(input).m();
+        let windows = tape.gather(input, self.input.size(), windows(self.input, self.size, self.stride), self.size * self.size);
+        let max = tape.max_rows(windows);
+        tape.reshape(max, batch_size, self.output_shape().size())
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&[model_file::MAX_POOL_2D])?;
+        write_pooling(writer, self.input, self.size, self.stride)
+    }
+}
+
+impl Layer for AvgPool2D {
+    fn name(&self) -> String {
+        format!("AvgPool2D {s}×{s} {} → {}", self.input, self.output_shape(), s = self.size)
+    }
+
+    fn sizes(&self) -> Option<(usize, usize)> {
+        Some((self.input.size(), self.output_shape().size()))
+    }
+
+    fn forward(&self, tape: &mut Tape, input: Var, _rng: Option<&mut dyn RngCore>) -> Var {
+        let batch_size = tape.value(input).m();
+        let windows = tape.gather(input, self.input.size(), windows(self.input, self.size, self.stride), self.size * self.size);
+        let sums = tape.sum_rows(windows);
+        let means = tape.scale(sums, 1.0 / (self.size * self.size) as F);
+        tape.reshape(means, batch_size, self.output_shape().size())
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&[model_file::AVG_POOL_2D])?;
+        write_pooling(writer, self.input, self.size, self.stride)
+    }
+}
+
+fn output_shape(input: Shape, size: usize, stride: usize) -> Shape {
+    Shape::new(input.channels, (input.height - size) / stride + 1, (input.width - size) / stride + 1)
+}
+
+// for `Tape::gather`: the indices (into a sample) of the values in each window, window by
+// window in output order.
+fn windows(input: Shape, size: usize, stride: usize) -> Vec<Option<usize>> {
+    let output = output_shape(input, size, stride);
+    let mut windows = Vec::with_capacity(output.size() * size * size);
+
+    for c in 0..output.channels {
+        for oy in 0..output.height {
+            for ox in 0..output.width {
+                windows.extend((0..size * size).map(|i| {
+                    let y = oy * stride + i / size;
+                    let x = ox * stride + i % size;
+                    Some((c * input.height + y) * input.width + x)
+                }));
+            }
+        }
+    }
+
+    windows
+}
+
+fn read_pooling(reader: &mut dyn Read) -> io::Result<(Shape, usize, usize)> {
+    let input = Shape::read_from(reader)?;
+    let size = read_u32(reader)? as usize;
+    let stride = read_u32(reader)? as usize;
+
+    if stride == 0 || size == 0 || size > input.height.min(input.width) {
+        return Err(model_file::invalid_data("pooling window doesn't fit its input"));
+    }
+
+    Ok((input, size, stride))
+}
+
+fn write_pooling(writer: &mut dyn Write, input: Shape, size: usize, stride: usize) -> io::Result<()> {
+    input.write_to(writer)?;
+    write_u32(writer, size as u32)?;
+    write_u32(writer, stride as u32)
+}
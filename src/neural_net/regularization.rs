@@ -0,0 +1,25 @@
+
+use super::math::F;
+
+/// Penalty on large weights (and other parameters), added to the loss: `l1 Σ|w| + (l2 / 2) Σw²`.
+///
+/// Only `l1` set is L1 (lasso), only `l2` set is L2 (weight decay: every step shrinks
+/// the weights by a factor `1 - learning_rate * l2`), and both set is elastic net.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Regularization {
+    pub l1: F,
+    pub l2: F,
+}
+
+impl Regularization {
+    pub fn is_none(&self) -> bool {
+        self.l1 == 0.0 && self.l2 == 0.0
+    }
+
+    /// Derivative of the penalty with respect to one weight.
+    pub fn gradient(&self, w: F) -> F {
+        // the L1 term isn't differentiable at 0; use 0 there so weights can stay at exactly 0.
+        let sign = if w > 0.0 { 1.0 } else if w < 0.0 { -1.0 } else { 0.0 };
+        self.l1 * sign + self.l2 * w
+    }
+}
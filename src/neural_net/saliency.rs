@@ -0,0 +1,96 @@
+use super::NeuralNet;
+use super::math::{ F, Matrix };
+use super::autograd::{ Tape, Var };
+
+use std::iter::zip;
+
+// what a blank image scales to, as the starting point of integrated gradients.
+const BLANK: F = 0.01;
+
+/// How much each input value is credited with an output of the net.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Attribution {
+    /// The gradient of the output with respect to the input: how much nudging each value
+    /// would change it.
+    Gradient,
+    /// The gradient times the input, so values that are (nearly) blank get no credit.
+    GradientTimesInput,
+    /// Sundararajan et al.: the gradient averaged along a straight path from a blank image to
+    /// the input, times the difference between the two. The credits add up to how much the
+    /// output differs from the blank image's.
+    IntegratedGradients { steps: usize },
+}
+
+impl Attribution {
+    pub const ALL: [Attribution; 3] = [
+        Attribution::Gradient,
+        Attribution::GradientTimesInput,
+        Attribution::IntegratedGradients { steps: 32 },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Attribution::Gradient => "Gradient",
+            Attribution::GradientTimesInput => "Gradient × Input",
+            Attribution::IntegratedGradients { .. } => "Integrated Gradients",
+        }
+    }
+}
+
+impl NeuralNet {
+    /// The credit every input value gets for output `class` of `input`, as in inference
+    /// mode.
+    pub fn attribution(&self, input: &[F], class: usize, method: Attribution) -> Vec<F> {
+        match method {
+            Attribution::Gradient => self.output_gradient(input, class),
+            Attribution::GradientTimesInput => zip(self.output_gradient(input, class), input).map(|(g, x)| g * x).collect(),
+            Attribution::IntegratedGradients { steps } => {
+                assert!(steps > 0);
+
+                // the midpoint of each step along the path, all in one batch.
+                let path: Vec<Vec<F>> = (0..steps)
+                    .map(|step| {
+                        let alpha = (step as F + 0.5) / steps as F;
+                        input.iter().map(|x| BLANK + alpha * (x - BLANK)).collect()
+                    })
+                    .collect();
+
+                let gradients = self.input_gradients(Matrix::from_rows(path), |tape, output| record_output(tape, output, class));
+
+                let mut average = vec![0.0; input.len()];
+                for gradient in gradients.iter_row_slices() {
+                    for (average, g) in zip(&mut average, gradient) {
+                        *average += g / steps as F;
+                    }
+                }
+                zip(average, input).map(|(g, x)| g * (x - BLANK)).collect()
+            }
+        }
+    }
+
+    // gradient of output `class` with respect to the input.
+    fn output_gradient(&self, input: &[F], class: usize) -> Vec<F> {
+        let inputs = Matrix::from_values(input.to_vec(), 1, input.len());
+        self.input_gradients(inputs, |tape, output| record_output(tape, output, class)).get_row(0)
+    }
+
+    /// Backpropagates through a batch of inputs (one per row) as in inference mode, from a
+    /// single value that `objective` records from the outputs. Returns the gradient with
+    /// respect to each input.
+    pub(super) fn input_gradients(&self, inputs: Matrix, objective: impl FnOnce(&mut Tape, Var) -> Var) -> Matrix {
+        let mut cache = self.forward(inputs, None);
+        let objective = cache.record(objective);
+
+        // the weights' gradients come along, but aren't needed.
+        let mut gradients = self.zero_gradients();
+        self.layers.backward(&cache, objective, &mut gradients)
+    }
+}
+
+// output `class` of every sample, summed; each sample's gradient is its own.
+fn record_output(tape: &mut Tape, output: Var, class: usize) -> Var {
+    let (m, n) = (tape.value(output).m(), tape.value(output).n());
+    let one_hot = tape.leaf(Matrix::from_values((0..m * n).map(|i| if i % n == class { 1.0 } else { 0.0 }).collect::<Vec<F>>(), m, n));
+    let class_outputs = tape.mul(output, one_hot);
+    tape.sum(class_outputs)
+}
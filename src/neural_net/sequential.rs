@@ -0,0 +1,163 @@
+use super::Gradients;
+use super::math::{ F, Matrix };
+use super::layer::{ Layer, LayerGradients };
+use super::init::Initialization;
+use super::autograd::{ Tape, Var };
+
+use rand::RngCore;
+
+use std::iter::zip;
+use std::ops::Range;
+
+/// Layers applied one after another, each to the output of the one before.
+#[derive(Debug, Clone, Default)]
+pub struct Sequential {
+    layers: Vec<Box<dyn Layer>>,
+}
+
+/// A batch fed through a `Sequential`, recorded on a tape.
+#[derive(Debug)]
+pub struct ForwardCache {
+    tape: Tape,
+    // the input, followed by the output of every layer.
+    activations: Vec<Var>,
+    // what each layer added to `tape.parameters()` and `tape.batch_statistics()`.
+    parameters: Vec<Range<usize>>,
+    statistics: Vec<Range<usize>>,
+}
+
+impl ForwardCache {
+    /// The input for `0`, and the output of layer `i - 1` for `i`; one row per sample.
+    pub fn activation(&self, i: usize) -> &Matrix {
+        self.tape.value(self.activations[i])
+    }
+
+    pub fn activations(&self) -> impl Iterator<Item = &Matrix> {
+        self.activations.iter().map(|&var| self.tape.value(var))
+    }
+
+    pub fn output(&self) -> &Matrix {
+        self.tape.value(*self.activations.last().unwrap())
+    }
+
+    /// Records something worked out from the output on the tape, like a loss.
+    pub fn record(&mut self, f: impl FnOnce(&mut Tape, Var) -> Var) -> Var {
+        f(&mut self.tape, *self.activations.last().unwrap())
+    }
+
+    pub fn value(&self, var: Var) -> &Matrix {
+        self.tape.value(var)
+    }
+}
+
+impl Sequential {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, layer: impl Layer + 'static) {
+        self.layers.push(Box::new(layer));
+    }
+
+    pub fn insert(&mut self, index: usize, layer: impl Layer + 'static) {
+        self.layers.insert(index, Box::new(layer));
+    }
+
+    pub fn remove(&mut self, index: usize) -> Box<dyn Layer> {
+        self.layers.remove(index)
+    }
+
+    pub fn layers(&self) -> &[Box<dyn Layer>] {
+        &self.layers
+    }
+
+    pub fn layers_mut(&mut self) -> &mut [Box<dyn Layer>] {
+        &mut self.layers
+    }
+
+    /// Feeds a batch (one sample per row) through every layer. `rng` is only given while
+    /// training; see `Layer::forward`.
+    pub fn forward(&self, input: Matrix, mut rng: Option<&mut dyn RngCore>) -> ForwardCache {
+        let mut tape = Tape::new();
+        let mut activations = Vec::with_capacity(self.layers.len() + 1);
+        let mut parameters = Vec::with_capacity(self.layers.len());
+        let mut statistics = Vec::with_capacity(self.layers.len());
+
+        activations.push(tape.leaf(input));
+
+        for layer in &self.layers {
+            let (parameters_start, statistics_start) = (tape.parameters().len(), tape.batch_statistics().len());
+
+            let output = layer.forward(&mut tape, *activations.last().unwrap(), rng.as_mut().map(|rng| &mut **rng as &mut dyn RngCore));
+            activations.push(output);
+
+            parameters.push(parameters_start..tape.parameters().len());
+            statistics.push(statistics_start..tape.batch_statistics().len());
+        }
+
+        ForwardCache {
+            tape,
+            activations,
+            parameters,
+            statistics,
+        }
+    }
+
+    /// Backpropagates `result`, a single value recorded on the cache's tape (like the loss),
+    /// adding the gradient of every layer's parameters onto `gradients` along with the batch
+    /// statistics the layers used. Returns the gradient with respect to the input.
+    pub fn backward(&self, cache: &ForwardCache, result: Var, gradients: &mut Gradients) -> Matrix {
+        let tape = &cache.tape;
+        let tape_gradients = tape.backward(result);
+
+        for (i, layer_gradients) in gradients.layers.iter_mut().enumerate() {
+            let parameters = &tape.parameters()[cache.parameters[i].clone()];
+            assert_eq!(parameters.len(), layer_gradients.parameters.len(), "{} didn't record all its parameters", self.layers[i].name());
+
+            for (&parameter, sums) in zip(parameters, &mut layer_gradients.parameters) {
+                // `None` when the result doesn't depend on it.
+                if let Some(gradient) = tape_gradients.get(parameter) {
+                    for (sum, g) in zip(sums, gradient.get_raw_slice()) {
+                        *sum += g;
+                    }
+                }
+            }
+
+            let statistics = &tape.batch_statistics()[cache.statistics[i].clone()];
+            if !statistics.is_empty() {
+                layer_gradients.add_statistics(&statistics.iter().map(|s| s.as_slice()).collect::<Vec<_>>());
+            }
+        }
+
+        let input = cache.activation(0);
+        tape_gradients.get(cache.activations[0]).cloned().unwrap_or_else(|| Matrix::new(input.m(), input.n()))
+    }
+
+    pub fn zero_gradients(&self) -> Gradients {
+        Gradients {
+            layers: self.layers.iter().map(|layer| LayerGradients::zeros(layer.as_ref())).collect(),
+        }
+    }
+
+    pub fn parameters(&self) -> Vec<&[F]> {
+        self.layers.iter().flat_map(|layer| layer.parameters()).collect()
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<&mut [F]> {
+        self.layers.iter_mut().flat_map(|layer| layer.parameters_mut()).collect()
+    }
+
+    pub fn statistics(&self) -> Vec<&[F]> {
+        self.layers.iter().flat_map(|layer| layer.statistics()).collect()
+    }
+
+    pub fn statistics_mut(&mut self) -> Vec<&mut [F]> {
+        self.layers.iter_mut().flat_map(|layer| layer.statistics_mut()).collect()
+    }
+
+    pub fn initialize(&mut self, initialization: Initialization, rng: &mut dyn RngCore) {
+        for layer in &mut self.layers {
+            layer.initialize(initialization, rng);
+        }
+    }
+}
@@ -0,0 +1,1354 @@
+
+use super::*;
+use super::parallel::ParallelTrainer;
+use super::hogwild::HogwildTrainer;
+use super::activation::Activation;
+use super::loss::Loss;
+use super::regularization::Regularization;
+use super::conv::{ Conv2D, Shape };
+use super::pooling::{ AvgPool2D, MaxPool2D };
+use super::layer::Layer;
+use super::autograd::{ Tape, Var };
+use super::init::Initialization;
+use super::clipping::GradientClipping;
+use super::optimizer::Optimizer;
+use super::health::NonFinite;
+use super::saliency::Attribution;
+use super::adversarial::Attack;
+use super::history::History;
+use super::validation::{ self, Evaluation, EarlyStopping, EarlyStopper, Metric };
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::Duration;
+
+
+// small random dataset so tests run quickly; labels must fit the 10 outputs.
+fn generate_data(count: usize, input_size: usize, seed: u64) -> Vec<NNData> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..count)
+        .map(|_| NNData {
+            data: (0..input_size).map(|_| rng.random()).collect(),
+            label: rng.random_range(0..10),
+        })
+        .collect()
+}
+
+// the label is the brightest of the first 10 pixels, so there is something to learn. The
+// training tests use it too.
+pub(crate) fn generate_learnable_data(count: usize, input_size: usize, seed: u64) -> Vec<NNData> {
+    let mut data = generate_data(count, input_size, seed);
+    for data_point in &mut data {
+        data_point.data.iter_mut().for_each(|x| *x /= 4);
+        data_point.data[data_point.label] = 255;
+    }
+    data
+}
+
+fn generate_net(net_structure: impl Vector<usize>, seed: u64) -> NeuralNet {
+    let mut nn = NeuralNet::with_structure(net_structure);
+    let mut rng = StdRng::seed_from_u64(seed);
+    for weights in nn.parameters_mut() {
+        weights.iter_mut().for_each(|x| *x = rng.random_range(-0.5..0.5));
+    }
+    nn
+}
+
+#[test]
+fn test_parallel_gradients_match_serial() {
+    let data = Arc::new(generate_data(37, 8, 1));
+    let nn = Arc::new(generate_net(vec![8, 12, 10], 2));
+    let batch: Vec<usize> = (0..data.len()).collect();
+
+    let mut serial = nn.zero_gradients();
+    for data_point in data.iter() {
+        nn.accumulate_gradients(&[data_point], &mut serial, &mut rng());
+    }
+    serial.scale(1.0 / data.len() as F);
+
+    for threads in [1, 2, 3, 8] {
+        let (parallel, errors) = ParallelTrainer::new(threads, data.len(), 0)
+            .compute_gradients(&nn, &data, &batch);
+
+        assert_eq!(errors.len(), data.len());
+
+        for (s, p) in zip(serial.parameters(), parallel.parameters()) {
+            for (x, y) in zip(s, p) {
+                assert!((x - y).abs() < 0.00001);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parallel_batch_norm_never_normalizes_single_samples() {
+    let data = Arc::new(generate_data(7, 8, 5));
+    let mut nn = generate_net(vec![8, 12, 10], 6);
+    nn.set_batch_norm(0, true);
+    let batch: Vec<usize> = (0..data.len()).collect();
+
+    // 8 threads would take one sample each, and fall back on the running statistics.
+    let (gradients, errors) = ParallelTrainer::new(8, data.len(), 0).compute_gradients(&Arc::new(nn), &data, &batch);
+    assert_eq!(errors.len(), data.len());
+    assert!(gradients.batch_statistics().is_some());
+}
+
+#[test]
+fn test_parallel_training_is_deterministic() {
+    let data = Arc::new(generate_data(100, 8, 3));
+
+    let run = || {
+        let mut nn = Arc::new(generate_net(vec![8, 12, 10], 4));
+        let mut trainer = ParallelTrainer::new(4, 16, 42);
+        for _ in 0..20 {
+            let batch = trainer.next_batch(data.len());
+            let (gradients, _) = trainer.compute_gradients(&nn, &data, &batch);
+            // the workers are done with it, so this doesn't copy the net.
+            let before = Arc::as_ptr(&nn);
+            Arc::make_mut(&mut nn).apply_gradients(&gradients);
+            assert_eq!(Arc::as_ptr(&nn), before);
+        }
+        nn
+    };
+
+    let (a, b) = (run(), run());
+    assert_eq!(a.parameters(), b.parameters());
+}
+
+// mean loss over a dataset, measured the same way `train_one` reports it.
+fn mean_loss(nn: &NeuralNet, data: &[NNData]) -> F {
+    data.iter().map(|data_point| nn.loss(data_point)).sum::<F>() / data.len() as F
+}
+
+#[test]
+fn test_hogwild_converges_like_train_one() {
+    let data = Arc::new(generate_learnable_data(500, 12, 5));
+    let mut initial = generate_net(vec![12, 16, 10], 6);
+    initial.set_learning_rate(0.5);
+
+    let threads = 4;
+    let samples_per_thread = 1500;
+
+    // single-threaded baseline, same total number of samples.
+    let mut serial = initial.clone();
+    let mut rng = StdRng::seed_from_u64(7);
+    for _ in 0..threads * samples_per_thread {
+        serial.train_one(&data[rng.random_range(0..data.len())]);
+    }
+
+    let (losses_tx, losses_rx) = mpsc::channel();
+    let trainer = HogwildTrainer::start(&initial, Arc::clone(&data), threads, 1, Some(samples_per_thread), 7, losses_tx);
+    while !trainer.is_finished() {
+        thread::sleep(Duration::from_millis(1));
+    }
+    let mut hogwild = initial.clone();
+    trainer.shared_weights().load_into(&mut hogwild);
+    trainer.stop();
+
+    assert_eq!(losses_rx.try_iter().flatten().count(), threads * samples_per_thread);
+
+    let initial_loss = mean_loss(&initial, &data);
+    let serial_loss = mean_loss(&serial, &data);
+    let hogwild_loss = mean_loss(&hogwild, &data);
+
+    assert!(serial_loss < 0.5 * initial_loss, "initial: {initial_loss}, train_one: {serial_loss}");
+    assert!(hogwild_loss < 0.5 * initial_loss, "initial: {initial_loss}, hogwild: {hogwild_loss}");
+    assert!(hogwild_loss < 1.5 * serial_loss, "train_one: {serial_loss}, hogwild: {hogwild_loss}");
+}
+
+// the loss `train_one` minimises: squared error against the target values.
+fn squared_error(nn: &NeuralNet, data_point: &NNData) -> F {
+    let output = nn.image_to_prediction(scale_and_normalize_data(&data_point.data));
+    zip(output, target_values(data_point.label)).map(|(o, t)| (o - t).powi(2)).sum()
+}
+
+#[test]
+fn test_gradients_match_finite_differences() {
+    let data = generate_data(3, 6, 8);
+    let mut nn = generate_net(vec![6, 5, 4, 10], 9);
+
+    let epsilon = 0.01;
+
+    for data_point in &data {
+        let mut gradients = nn.zero_gradients();
+        nn.accumulate_gradients(&[data_point], &mut gradients, &mut rng());
+        let analytic: Vec<Vec<F>> = gradients.parameters().iter().map(|g| g.to_vec()).collect();
+
+        for (layer, analytic) in analytic.iter().enumerate() {
+            for (i, &analytic) in analytic.iter().enumerate() {
+                let w = nn.parameters()[layer][i];
+
+                nn.parameters_mut()[layer][i] = w + epsilon;
+                let loss_plus = squared_error(&nn, data_point);
+                nn.parameters_mut()[layer][i] = w - epsilon;
+                let loss_minus = squared_error(&nn, data_point);
+                nn.parameters_mut()[layer][i] = w;
+
+                let numerical = (loss_plus - loss_minus) / (2.0 * epsilon);
+
+                assert!(
+                    (numerical - analytic).abs() <= 0.001 + 0.01 * numerical.abs().max(analytic.abs()),
+                    "layer {layer}, weight {i}: numerical {numerical}, analytic {analytic}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_train_one_matches_gradient_step() {
+    let data = generate_data(1, 8, 10);
+    let nn = generate_net(vec![8, 12, 10], 11);
+
+    let mut stepped = nn.clone();
+    let mut gradients = nn.zero_gradients();
+    let expected_error = stepped.accumulate_gradients(&[&data[0]], &mut gradients, &mut rng())[0];
+    stepped.apply_gradients(&gradients);
+
+    let mut trained = nn.clone();
+    let error = trained.train_one(&data[0]);
+
+    assert_eq!(error, expected_error);
+    for (a, b) in zip(trained.parameters(), stepped.parameters()) {
+        for (x, y) in zip(a, b) {
+            assert!((x - y).abs() < 0.000001);
+        }
+    }
+}
+
+#[test]
+fn test_gradient_check_every_activation_and_loss() {
+    let data = generate_data(3, 6, 12);
+
+    for hidden in Activation::ALL {
+        for output in Activation::ALL {
+            for loss in Loss::ALL {
+                // the cross entropies take the log of the outputs, so they need outputs in (0, 1).
+                if loss != Loss::SquaredError && !matches!(output, Activation::Sigmoid | Activation::Softmax) {
+                    continue;
+                }
+
+                let mut nn = generate_net(vec![6, 7, 5, 10], 1);
+                nn.set_activation(0, hidden);
+                nn.set_activation(1, hidden);
+                nn.set_activation(2, output);
+                nn.set_loss(loss);
+
+                // ReLU's kink at 0 means the odd weight whose perturbation crosses it will
+                // disagree, so only hold ReLU nets to the mean.
+                let has_kink = hidden == Activation::Relu || output == Activation::Relu;
+
+                for data_point in &data {
+                    let report = nn.gradient_check(data_point, 0.01, 100);
+
+                    assert_eq!(report.layers.len(), 3);
+                    assert_eq!(report.layers[0].checked, 42);
+
+                    if has_kink {
+                        for layer in &report.layers {
+                            assert!(
+                                layer.mean_relative_error < 0.05,
+                                "hidden {hidden:?}, output {output:?}, loss {loss:?}: {report:?}"
+                            );
+                        }
+                    } else {
+                        assert!(
+                            report.max_relative_error() < 0.1,
+                            "hidden {hidden:?}, output {output:?}, loss {loss:?}: {report:?}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_gradient_check_subsamples_large_layers() {
+    let data = generate_data(1, 8, 14);
+    let nn = generate_net(vec![8, 12, 10], 15);
+
+    let report = nn.gradient_check(&data[0], 0.01, 10);
+
+    assert_eq!(report.layers[0].checked, 10); // 96 weights, every 10th
+    assert_eq!(report.layers[1].checked, 10); // 120 weights, every 12th
+}
+
+#[test]
+fn test_training_loss_leaves_out_regularization() {
+    let mut nn = NeuralNet::with_structure(vec![2, 2, 10]);
+    nn.parameters_mut()[0].copy_from_slice(&[1.0, -2.0, 0.0, 3.0]);
+    nn.set_regularization(Regularization { l1: 0.1, l2: 0.1 });
+
+    // the reported loss is the one the validation split gets, without the penalty.
+    let data = generate_data(1, 2, 16);
+    let loss = nn.loss(&data[0]);
+    assert_eq!(nn.clone().train_one(&data[0]), loss);
+}
+
+#[test]
+fn test_regularization_shrinks_weights() {
+    let mut nn = NeuralNet::with_structure(vec![2, 2, 10]);
+    nn.parameters_mut()[0].copy_from_slice(&[1.0, -2.0, 0.0, 3.0]);
+    nn.set_learning_rate(0.1);
+    let zero = nn.zero_gradients();
+
+    // weight decay: w <- w (1 - learning_rate * l2)
+    let mut l2 = nn.clone();
+    l2.set_regularization(Regularization { l1: 0.0, l2: 0.5 });
+    l2.apply_gradients(&zero);
+    assert_eq!(l2.parameters()[0], [0.95, -1.9, 0.0, 2.85]);
+
+    // L1 moves every non-zero weight towards 0 by the same amount.
+    let mut l1 = nn.clone();
+    l1.set_regularization(Regularization { l1: 0.5, l2: 0.0 });
+    l1.apply_gradients(&zero);
+    assert_eq!(l1.parameters()[0], [0.95, -1.95, 0.0, 2.95]);
+}
+
+#[test]
+fn test_dropout_only_applies_in_training_mode() {
+    let data = generate_data(1, 8, 17);
+    let input = scale_and_normalize_data(&data[0].data);
+
+    let mut nn = generate_net(vec![8, 200, 10], 18);
+    let expected = nn.image_to_prediction(input.clone());
+
+    nn.set_dropout(0, 0.25);
+    assert_eq!(nn.layers().layers().len(), 5);
+    assert_eq!(nn.dropout(0), 0.25);
+
+    // predictions never drop anything.
+    assert_eq!(nn.image_to_prediction(input.clone()), expected);
+
+    // the dropout layer comes after the first dense layer and its activation.
+    let before_and_after_dropout = |cache: &ForwardCache| (cache.activation(2).get_row(0), cache.activation(3).get_row(0));
+
+    nn.set_mode(Mode::Inference);
+    let cache = nn.forward(Matrix::from_values(input.clone(), 1, 8), Some(&mut StdRng::seed_from_u64(19)));
+    let (before, after) = before_and_after_dropout(&cache);
+    assert_eq!(before, after);
+
+    nn.set_mode(Mode::Training);
+    let cache = nn.forward(Matrix::from_values(input.clone(), 1, 8), Some(&mut StdRng::seed_from_u64(19)));
+    let (before, after) = before_and_after_dropout(&cache);
+    assert_eq!(after.len(), 200);
+
+    // inverted dropout: kept outputs are scaled by 1 / (1 - p).
+    let dropped = after.iter().filter(|&&a| a == 0.0).count();
+    assert!(zip(&before, &after).all(|(b, a)| *a == 0.0 || (a - b / 0.75).abs() < 0.00001));
+    assert!((25..75).contains(&dropped));
+
+    nn.set_dropout(0, 0.0);
+    assert_eq!(nn.layers().layers().len(), 4);
+}
+
+#[test]
+fn test_dropout_mask_reused_in_backprop() {
+    let data = generate_data(1, 8, 20);
+    let mut nn = generate_net(vec![8, 12, 10], 21);
+    nn.set_dropout(0, 0.5);
+
+    // `accumulate_gradients` draws the same mask as a forward pass with the same seed.
+    let kept: Vec<bool> = nn.forward(batch_inputs(&[&data[0]]), Some(&mut StdRng::seed_from_u64(22)))
+        .activation(3).get_row(0).iter().map(|&a| a != 0.0).collect();
+    assert!(kept.contains(&false));
+
+    let mut gradients = nn.zero_gradients();
+    nn.accumulate_gradients(&[&data[0]], &mut gradients, &mut StdRng::seed_from_u64(22));
+    let gradients = gradients.parameters();
+
+    for (unit, &kept) in kept.iter().enumerate() {
+        let into_unit = &gradients[0][unit * 8..(unit + 1) * 8];
+        let out_of_unit: Vec<F> = (0..10).map(|output| gradients[1][output * 12 + unit]).collect();
+
+        if kept {
+            assert!(into_unit.iter().any(|&g| g != 0.0));
+        } else {
+            assert!(into_unit.iter().chain(&out_of_unit).all(|&g| g == 0.0));
+        }
+    }
+}
+
+#[test]
+fn test_per_layer_settings_find_their_layers() {
+    let mut nn = NeuralNet::with_structure(vec![4, 3, 2]);
+    assert_eq!(nn.num_dense_layers(), 2);
+
+    nn.set_dropout(0, 0.5);
+    nn.set_batch_norm(0, true);
+    nn.set_activation(0, Activation::Relu);
+    nn.set_batch_norm(1, true);
+    nn.set_activation(1, Activation::Softmax);
+
+    let names: Vec<String> = nn.layers().layers().iter().map(|layer| layer.name()).collect();
+    assert_eq!(names, [
+        "Dense 4 → 3", "Batch Norm 3", "Relu", "Dropout 0.5",
+        "Dense 3 → 2", "Batch Norm 2", "Softmax",
+    ]);
+
+    assert_eq!(nn.activation(0), Some(Activation::Relu));
+    assert_eq!(nn.dropout(1), 0.0);
+    assert!(nn.has_batch_norm(1));
+
+    // setting something that's already set doesn't add another layer.
+    nn.set_batch_norm(0, true);
+    nn.set_dropout(0, 0.5);
+    assert_eq!(nn.layers().layers().len(), 7);
+
+    nn.set_batch_norm(0, false);
+    nn.set_dropout(0, 0.0);
+    assert_eq!(nn.layers().layers().len(), 5);
+    assert_eq!(nn.activation(0), Some(Activation::Relu));
+}
+
+// summed loss over a batch, normalized with the statistics of the batch itself.
+fn batch_loss(nn: &NeuralNet, batch: &[&NNData]) -> F {
+    let mut cache = nn.forward(batch_inputs(batch), Some(&mut rng()));
+    let losses = cache.record(|tape, output| nn.record_loss(tape, output, batch_targets(batch)));
+    cache.value(losses).get_raw_slice().iter().sum()
+}
+
+#[test]
+fn test_batch_norm_gradients_match_finite_differences() {
+    let data = generate_data(5, 6, 23);
+    let batch: Vec<&NNData> = data.iter().collect();
+
+    let mut nn = generate_net(vec![6, 5, 4, 10], 24);
+    nn.set_batch_norm(0, true);
+    nn.set_batch_norm(1, true);
+
+    // move the scale and shift away from the identity so their gradients matter.
+    let mut rng = StdRng::seed_from_u64(25);
+    for parameters in nn.parameters_mut() {
+        parameters.iter_mut().for_each(|x| *x += rng.random_range(-0.5..0.5));
+    }
+
+    let mut gradients = nn.zero_gradients();
+    nn.accumulate_gradients(&batch, &mut gradients, &mut rand::rng());
+    let analytic: Vec<Vec<F>> = gradients.parameters().iter().map(|g| g.to_vec()).collect();
+
+    let epsilon = 0.01;
+
+    for (index, analytic) in analytic.iter().enumerate() {
+        for (i, &analytic) in analytic.iter().enumerate() {
+            let x = nn.parameters()[index][i];
+
+            nn.parameters_mut()[index][i] = x + epsilon;
+            let loss_plus = batch_loss(&nn, &batch);
+            nn.parameters_mut()[index][i] = x - epsilon;
+            let loss_minus = batch_loss(&nn, &batch);
+            nn.parameters_mut()[index][i] = x;
+
+            let numerical = (loss_plus - loss_minus) / (2.0 * epsilon);
+
+            assert!(
+                (numerical - analytic).abs() <= 0.002 + 0.02 * numerical.abs().max(analytic.abs()),
+                "parameters {index}, value {i}: numerical {numerical}, analytic {analytic}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_batch_norm_running_statistics() {
+    let data = generate_data(64, 6, 26);
+    let batch: Vec<&NNData> = data.iter().collect();
+
+    let mut nn = generate_net(vec![6, 5, 10], 27);
+    nn.set_batch_norm(0, true);
+    nn.set_learning_rate(0.0);
+
+    // the statistics the running averages should converge to.
+    let z = nn.forward(batch_inputs(&batch), None).activation(1).clone();
+    let mean: Vec<F> = (0..z.n()).map(|j| z.get_col(j).iter().sum::<F>() / z.m() as F).collect();
+    let variance: Vec<F> = (0..z.n())
+        .map(|j| z.get_col(j).iter().map(|x| (x - mean[j]).powi(2)).sum::<F>() / (z.m() - 1) as F)
+        .collect();
+
+    for _ in 0..100 {
+        let mut gradients = nn.zero_gradients();
+        nn.accumulate_gradients(&batch, &mut gradients, &mut rng());
+        nn.apply_gradients(&gradients);
+    }
+
+    let statistics = nn.statistics();
+    for j in 0..mean.len() {
+        assert!((statistics[0][j] - mean[j]).abs() < 0.001);
+        assert!((statistics[1][j] - variance[j]).abs() < 0.001);
+    }
+
+    // predictions use the running statistics, so they don't depend on the rest of the batch.
+    let single = nn.forward(batch_inputs(&batch[..1]), None);
+    let whole = nn.forward(batch_inputs(&batch), None);
+    assert_eq!(single.output().get_row(0), whole.output().get_row(0));
+
+    // training on one sample has no batch statistics to use or record.
+    let mut gradients = nn.zero_gradients();
+    nn.accumulate_gradients(&batch[..1], &mut gradients, &mut rng());
+    assert!(gradients.batch_statistics().is_none());
+}
+
+#[test]
+fn test_save_and_load_round_trip() {
+    let data = generate_data(16, 8, 28);
+    let batch: Vec<&NNData> = data.iter().collect();
+
+    let mut nn = generate_net(vec![8, 12, 6, 10], 29);
+    nn.set_activation(1, Activation::Relu);
+    nn.set_activation(2, Activation::Softmax);
+    nn.set_loss(Loss::CrossEntropy);
+    nn.set_dropout(0, 0.3);
+    nn.set_batch_norm(1, true);
+    nn.set_regularization(Regularization { l1: 0.001, l2: 0.002 });
+    nn.set_learning_rate(0.2);
+    nn.set_optimizer(Optimizer::Adam { beta1: 0.8, beta2: 0.99, epsilon: 1e-7 });
+    nn.set_gradient_clipping(GradientClipping { value: None, norm: Some(5.0) });
+    nn.set_initialization(Initialization::Constant(0.25));
+
+    // a few steps so the batch norm statistics aren't just their starting values.
+    for _ in 0..3 {
+        let mut gradients = nn.zero_gradients();
+        nn.accumulate_gradients(&batch, &mut gradients, &mut rng());
+        gradients.scale(1.0 / batch.len() as F);
+        nn.apply_gradients(&gradients);
+    }
+
+    let mut bytes = Vec::new();
+    nn.write_to(&mut bytes).unwrap();
+    let loaded = NeuralNet::read_from(&mut bytes.as_slice()).unwrap();
+
+    let names = |nn: &NeuralNet| nn.layers().layers().iter().map(|layer| layer.name()).collect::<Vec<_>>();
+    assert_eq!(names(&loaded), names(&nn));
+    assert_eq!(loaded.statistics(), nn.statistics());
+    assert_eq!(loaded.parameters(), nn.parameters());
+    assert_eq!(loaded.loss, nn.loss);
+    assert_eq!(loaded.regularization, nn.regularization);
+    assert_eq!(loaded.learning_rate, nn.learning_rate);
+    assert_eq!(loaded.seed(), nn.seed());
+    assert_eq!(loaded.optimizer(), nn.optimizer());
+    assert_eq!(loaded.gradient_clipping(), nn.gradient_clipping());
+    assert_eq!(loaded.initialization(), nn.initialization());
+
+    for data_point in &data {
+        let input = scale_and_normalize_data(&data_point.data);
+        assert_eq!(loaded.image_to_prediction(input.clone()), nn.image_to_prediction(input));
+    }
+
+    // anything that isn't a whole model is rejected rather than half-loaded.
+    assert!(NeuralNet::read_from(&mut &bytes[..bytes.len() - 1]).is_err());
+    assert!(NeuralNet::read_from(&mut &b"not a model"[..]).is_err());
+}
+
+#[test]
+fn test_model_file_rejects_nets_that_cant_work() {
+    let invalid = |bytes: &[u8]| NeuralNet::read_from(&mut &bytes[..]).unwrap_err().kind() == std::io::ErrorKind::InvalidData;
+
+    let mut bytes = Vec::new();
+    NeuralNet::with_structure(vec![4, 3]).write_to(&mut bytes).unwrap();
+    let header = &bytes[..8];
+
+    let no_layers = [header, &0u32.to_le_bytes()].concat();
+    assert!(invalid(&no_layers));
+
+    // a size that would need more memory than there is.
+    let huge = [header, &1u32.to_le_bytes(), &[model_file::DENSE], &u32::MAX.to_le_bytes(), &u32::MAX.to_le_bytes()].concat();
+    assert!(invalid(&huge));
+
+    let mut layers = Sequential::new();
+    layers.push(Dense::new(4, 3));
+    layers.push(Activation::Sigmoid);
+    layers.push(Dense::new(5, 2));
+    let mut bytes = Vec::new();
+    NeuralNet::from_layers(layers).write_to(&mut bytes).unwrap();
+    assert!(invalid(&bytes));
+
+    let mut layers = Sequential::new();
+    layers.push(MaxPool2D::new(Shape::new(1, 4, 4), 2, 2));
+    let mut bytes = Vec::new();
+    NeuralNet::from_layers(layers).write_to(&mut bytes).unwrap();
+    assert!(invalid(&bytes));
+}
+
+#[test]
+fn test_load_rejects_nets_that_dont_fit_mnist() {
+    let path = std::env::temp_dir().join(format!("nn-from-scratch-{}-shapes.nn", std::process::id()));
+    let load = |nn: NeuralNet| {
+        nn.save(&path).unwrap();
+        let result = NeuralNet::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        result
+    };
+
+    assert!(load(NeuralNet::new()).is_ok());
+    assert_eq!(load(NeuralNet::with_structure(vec![4, 3])).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_sequential_mixes_layers() {
+    let data = generate_data(2, 6, 30);
+
+    let mut layers = Sequential::new();
+    layers.push(Dense::new(6, 8));
+    layers.push(Activation::Tanh);
+    layers.push(Dense::new(8, 8));
+    layers.push(BatchNorm::new(8));
+    layers.push(Activation::Sigmoid);
+    layers.push(Dropout::new(0.5));
+    layers.push(Dense::new(8, 10));
+    layers.push(Activation::Softmax);
+
+    let mut nn = NeuralNet::from_layers(layers);
+    nn.populate_seeded_weights(31);
+    nn.set_loss(Loss::CrossEntropy);
+
+    assert_eq!(nn.num_dense_layers(), 3);
+    assert_eq!(nn.image_to_prediction(scale_and_normalize_data(&data[0].data)).len(), 10);
+
+    for data_point in &data {
+        let report = nn.gradient_check(data_point, 0.01, 100);
+        let names: Vec<&str> = report.layers.iter().map(|layer| layer.name.as_str()).collect();
+        assert_eq!(names, ["Dense 6 → 8", "Dense 8 → 8", "Batch Norm 8", "Dense 8 → 10"]);
+        assert!(report.max_relative_error() < 0.1, "{report:?}");
+    }
+}
+
+// a layer's output for a batch, as predicted.
+fn layer_output(layer: &dyn Layer, input: &Matrix) -> Matrix {
+    let mut tape = Tape::new();
+    let input = tape.leaf(input.clone());
+    let output = layer.forward(&mut tape, input, None);
+    tape.value(output).clone()
+}
+
+#[test]
+fn test_conv_and_pooling_forward() {
+    // the second sample is the first one doubled.
+    let input = Matrix::from_rows(vec![(1..=9).map(|x| x as F).collect::<Vec<_>>(), (1..=9).map(|x| 2.0 * x as F).collect()]);
+    let shape = Shape::new(1, 3, 3);
+
+    let mut conv = Conv2D::new(shape, 1, 2, 1, 0);
+    conv.parameters_mut()[0].fill(1.0);
+    conv.parameters_mut()[1].fill(0.5);
+    assert_eq!(conv.output_shape(), Shape::new(1, 2, 2));
+    assert_eq!(layer_output(&conv, &input).get_raw_slice(), [12.5, 16.5, 24.5, 28.5, 24.5, 32.5, 48.5, 56.5]);
+
+    // with padding the filter also sits half outside the image, only seeing the corners there.
+    let mut padded = Conv2D::new(shape, 1, 2, 2, 1);
+    padded.parameters_mut()[0].fill(1.0);
+    assert_eq!(padded.output_shape(), Shape::new(1, 2, 2));
+    assert_eq!(layer_output(&padded, &input).get_row(0), [1.0, 5.0, 11.0, 28.0]);
+
+    assert_eq!(layer_output(&MaxPool2D::new(shape, 2, 1), &input).get_raw_slice(), [5.0, 6.0, 8.0, 9.0, 10.0, 12.0, 16.0, 18.0]);
+    assert_eq!(layer_output(&AvgPool2D::new(shape, 2, 1), &input).get_raw_slice(), [3.0, 4.0, 6.0, 7.0, 6.0, 8.0, 12.0, 14.0]);
+
+    // several output channels still go channel by channel.
+    let mut two_channels = Conv2D::new(shape, 2, 3, 1, 0);
+    two_channels.parameters_mut()[0][9..].fill(1.0);
+    two_channels.parameters_mut()[1].copy_from_slice(&[1.0, 2.0]);
+    assert_eq!(layer_output(&two_channels, &input).get_raw_slice(), [1.0, 47.0, 1.0, 92.0]);
+}
+
+#[test]
+fn test_conv_and_pooling_gradients_match_finite_differences() {
+    let data = generate_data(3, 2 * 6 * 6, 32);
+
+    // max pooling windows don't overlap, so a small change in the weights is less likely
+    // to move which value is the max (where the loss isn't differentiable).
+    let conv1 = Conv2D::new(Shape::new(2, 6, 6), 3, 3, 1, 1);
+    let max_pool = MaxPool2D::new(conv1.output_shape(), 2, 2);
+    let conv2 = Conv2D::new(max_pool.output_shape(), 4, 2, 1, 1);
+    let avg_pool = AvgPool2D::new(conv2.output_shape(), 2, 1);
+    let features = avg_pool.output_shape().size();
+
+    let mut layers = Sequential::new();
+    layers.push(conv1);
+    layers.push(Activation::Tanh);
+    layers.push(max_pool);
+    layers.push(conv2);
+    layers.push(Activation::Tanh);
+    layers.push(avg_pool);
+    layers.push(Dense::new(features, 10));
+    layers.push(Activation::Softmax);
+
+    let mut nn = NeuralNet::from_layers(layers);
+    nn.populate_seeded_weights(33);
+    nn.set_loss(Loss::CrossEntropy);
+
+    // the biases start at 0; move them so their gradients matter.
+    let mut rng = StdRng::seed_from_u64(34);
+    for parameters in nn.parameters_mut() {
+        parameters.iter_mut().for_each(|x| *x += rng.random_range(-0.5..0.5));
+    }
+
+    for data_point in &data {
+        let report = nn.gradient_check(data_point, 0.01, 100);
+        let names: Vec<&str> = report.layers.iter().map(|layer| layer.name.as_str()).collect();
+        assert_eq!(names, ["Conv2D 3×3 2×6×6 → 3×6×6", "Conv2D 2×2 3×3×3 → 4×4×4", "Dense 36 → 10"]);
+        assert!(report.max_relative_error() < 0.1, "{report:?}");
+    }
+}
+
+#[test]
+fn test_lenet_maps_a_batch_of_images_to_10_outputs() {
+    let data = generate_data(3, 28 * 28, 34);
+    let batch: Vec<&NNData> = data.iter().collect();
+
+    let mut nn = NeuralNet::lenet();
+    nn.populate_seeded_weights(34);
+
+    let cache = nn.forward(batch_inputs(&batch), None);
+    let output = cache.output();
+    assert_eq!((output.m(), output.n()), (3, 10));
+    // the softmax gives every image a distribution over the digits.
+    for row in output.iter_row_slices() {
+        assert!((row.iter().sum::<F>() - 1.0).abs() < 0.0001);
+    }
+}
+
+#[test]
+fn test_lenet_save_and_load_round_trip() {
+    let data = generate_data(2, 28 * 28, 35);
+
+    let mut nn = NeuralNet::lenet();
+    nn.populate_seeded_weights(36);
+
+    assert_eq!(nn.num_dense_layers(), 3);
+
+    // through a file, as the GUI saves and loads it.
+    let path = std::env::temp_dir().join(format!("nn-from-scratch-{}-lenet.nn", std::process::id()));
+    nn.save(&path).unwrap();
+    let loaded = NeuralNet::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    let loaded = loaded.unwrap();
+
+    let names = |nn: &NeuralNet| nn.layers().layers().iter().map(|layer| layer.name()).collect::<Vec<_>>();
+    assert_eq!(names(&loaded), names(&nn));
+    assert_eq!(loaded.parameters(), nn.parameters());
+    assert_eq!(loaded.loss, Loss::CrossEntropy);
+
+    for data_point in &data {
+        let input = scale_and_normalize_data(&data_point.data);
+        let prediction = nn.image_to_prediction(input.clone());
+        assert_eq!(prediction.len(), 10);
+        assert_eq!(loaded.image_to_prediction(input), prediction);
+    }
+}
+
+// a layer made up for the test, scaling every feature by a learned factor; it only says
+// how to record its output.
+#[derive(Debug, Clone)]
+struct Scale {
+    factors: Vec<F>,
+}
+
+impl Layer for Scale {
+    fn name(&self) -> String {
+        "Scale".to_string()
+    }
+
+    fn forward(&self, tape: &mut Tape, input: Var, _rng: Option<&mut dyn rand::RngCore>) -> Var {
+        let factors = tape.parameter(Matrix::from_values(self.factors.clone(), 1, self.factors.len()));
+        tape.mul(input, factors)
+    }
+
+    fn parameters(&self) -> Vec<&[F]> {
+        vec![&self.factors]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut [F]> {
+        vec![&mut self.factors]
+    }
+
+    // the model file has no tag for it.
+    fn write_to(&self, _writer: &mut dyn std::io::Write) -> std::io::Result<()> {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Scale layers can't be saved"))
+    }
+}
+
+#[test]
+fn test_new_layers_get_gradients_from_the_tape() {
+    let data = generate_data(3, 6, 37);
+
+    for loss in Loss::ALL {
+        let mut layers = Sequential::new();
+        layers.push(Dense::new(6, 7));
+        layers.push(Scale { factors: (0..7).map(|i| 0.5 + i as F / 7.0).collect() });
+        layers.push(Activation::Tanh);
+        layers.push(Dense::new(7, 10));
+        layers.push(Activation::Sigmoid);
+
+        let mut nn = NeuralNet::from_layers(layers);
+        nn.populate_seeded_weights(38);
+        nn.set_loss(loss);
+
+        for data_point in &data {
+            let report = nn.gradient_check(data_point, 0.01, 100);
+            let names: Vec<&str> = report.layers.iter().map(|layer| layer.name.as_str()).collect();
+            assert_eq!(names, ["Dense 6 → 7", "Scale", "Dense 7 → 10"]);
+            assert!(report.max_relative_error() < 0.05, "{loss:?}: {report:?}");
+        }
+    }
+}
+
+#[test]
+fn test_autograd_operations_match_finite_differences() {
+    let mut rng = StdRng::seed_from_u64(40);
+    let mut random_matrix = |m: usize, n: usize| Matrix::from_values((0..m * n).map(|_| rng.random_range(0.1..1.0)).collect::<Vec<F>>(), m, n);
+    let leaves = [random_matrix(3, 4), random_matrix(4, 5), random_matrix(1, 5), random_matrix(3, 5), random_matrix(2, 4)];
+
+    // uses every operation, so each one's gradient has to be right.
+    let record = |leaves: &[Matrix]| {
+        let mut tape = Tape::new();
+        let vars: Vec<Var> = leaves.iter().map(|leaf| tape.leaf(leaf.clone())).collect();
+
+        let product = tape.matmul(vars[0], vars[1]);
+        let biased = tape.add(product, vars[2]);
+        let activated = tape.activation(biased, Activation::Tanh);
+        let difference = tape.sub(activated, vars[3]);
+        let squared = tape.mul(difference, difference);
+        let probabilities = tape.activation(squared, Activation::Softmax);
+        let logs = tape.ln(probabilities);
+        let scaled = tape.scale(logs, -0.5);
+        let row_sums = tape.sum_rows(scaled);
+        let mean = tape.mean(row_sums);
+        let total = tape.sum(vars[3]);
+        let result = tape.add(mean, total);
+
+        // and the ones the layers use.
+        let transposed = tape.matmul_transpose(vars[0], vars[4]);
+        let column_means = tape.mean_columns(transposed);
+        let roots = tape.powf(column_means, 0.5);
+        let scaled_rows = tape.mul(transposed, roots);
+        let reshaped = tape.reshape(scaled_rows, 2, 3);
+        // the second "sample" picks its values in a different order, and one twice.
+        let picked = tape.gather(reshaped, 3, vec![Some(2), None, Some(0), Some(1), Some(2), Some(2)], 2);
+        let maxima = tape.max_rows(picked);
+        let layer_total = tape.sum(maxima);
+        let result = tape.add(result, layer_total);
+
+        (tape, vars, result)
+    };
+
+    let (tape, vars, result) = record(&leaves);
+    let gradients = tape.backward(result);
+
+    let epsilon = 0.01;
+    for (l, var) in vars.into_iter().enumerate() {
+        let analytic = gradients.get(var).unwrap();
+
+        for i in 0..leaves[l].get_raw_slice().len() {
+            let mut perturbed = leaves.to_vec();
+            perturbed[l].get_mut_raw_slice()[i] += epsilon;
+            let (tape, _, plus) = record(&perturbed);
+            perturbed[l].get_mut_raw_slice()[i] -= 2.0 * epsilon;
+            let (tape_minus, _, minus) = record(&perturbed);
+
+            let numerical = (tape.value(plus).get_raw_slice()[0] - tape_minus.value(minus).get_raw_slice()[0]) / (2.0 * epsilon);
+            let analytic = analytic.get_raw_slice()[i];
+            assert!((numerical - analytic).abs() < 0.01, "leaf {l}, value {i}: {numerical} vs {analytic}");
+        }
+    }
+}
+
+// the mean and variance of every value.
+fn moments(values: &[F]) -> (F, F) {
+    let mean = values.iter().sum::<F>() / values.len() as F;
+    let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<F>() / values.len() as F;
+    (mean, variance)
+}
+
+#[test]
+fn test_initializations_use_fan_in_and_fan_out() {
+    let (fan_in, fan_out) = (300, 100);
+    let expected_variances = [
+        (Initialization::XavierUniform, 2.0 / 400.0),
+        (Initialization::XavierNormal, 2.0 / 400.0),
+        (Initialization::HeUniform, 2.0 / 300.0),
+        (Initialization::HeNormal, 2.0 / 300.0),
+        (Initialization::LeCunNormal, 1.0 / 300.0),
+    ];
+
+    for (initialization, expected) in expected_variances {
+        // a dense layer's weights are outputs × inputs.
+        let mut dense = Dense::new(fan_in, fan_out);
+        dense.initialize(initialization, &mut StdRng::seed_from_u64(41));
+
+        let (mean, variance) = moments(dense.parameters()[0]);
+        assert!(mean.abs() < 0.01, "{initialization:?}: mean {mean}");
+        assert!((variance / expected - 1.0).abs() < 0.05, "{initialization:?}: variance {variance}, expected {expected}");
+    }
+
+    let uniform_bound = (6.0 / 400.0 as F).sqrt();
+    let mut dense = Dense::new(fan_in, fan_out);
+    dense.initialize(Initialization::XavierUniform, &mut StdRng::seed_from_u64(42));
+    assert!(dense.parameters()[0].iter().all(|x| x.abs() <= uniform_bound));
+
+    // a convolution's fan-in is everything under one filter: 3 channels of 5×5.
+    let mut conv = Conv2D::new(Shape::new(3, 12, 12), 40, 5, 1, 0);
+    conv.initialize(Initialization::HeNormal, &mut StdRng::seed_from_u64(43));
+    let (_, variance) = moments(conv.parameters()[0]);
+    assert!((variance * 75.0 / 2.0 - 1.0).abs() < 0.1, "conv variance {variance}");
+    assert!(conv.parameters()[1].iter().all(|&b| b == 0.0));
+
+    for (initialization, value) in [(Initialization::Zeros, 0.0), (Initialization::Constant(0.25), 0.25)] {
+        dense.initialize(initialization, &mut rng());
+        assert!(dense.parameters()[0].iter().all(|&x| x == value));
+    }
+}
+
+#[test]
+fn test_orthogonal_initialization() {
+    // wide matrices get orthonormal rows, tall ones orthonormal columns.
+    for (inputs, outputs) in [(12, 5), (5, 12), (7, 7)] {
+        let mut dense = Dense::new(inputs, outputs);
+        dense.initialize(Initialization::Orthogonal, &mut StdRng::seed_from_u64(44));
+
+        let weights = Matrix::from_values(dense.parameters()[0].to_vec(), outputs, inputs);
+        let product = if outputs <= inputs { weights.mul_transpose(&weights) } else { weights.tmul(&weights) };
+        let identity = Matrix::new_identity(outputs.min(inputs));
+
+        for (x, y) in zip(product.get_raw_slice(), identity.get_raw_slice()) {
+            assert!((x - y).abs() < 1e-4, "{inputs} → {outputs}: {product:?}");
+        }
+    }
+}
+
+#[test]
+fn test_seeded_initialization_is_reproducible() {
+    for initialization in Initialization::ALL {
+        let mut a = NeuralNet::with_structure(vec![8, 6, 10]);
+        let mut b = a.clone();
+        a.set_initialization(initialization);
+        b.set_initialization(initialization);
+
+        a.populate_seeded_weights(45);
+        b.populate_seeded_weights(45);
+        assert_eq!(a.parameters(), b.parameters(), "{initialization:?}");
+    }
+
+    let mut a = NeuralNet::with_structure(vec![8, 6, 10]);
+    let mut b = a.clone();
+    a.populate_seeded_weights(46);
+    b.populate_seeded_weights(47);
+    assert_ne!(a.parameters(), b.parameters());
+}
+
+// a fresh dropout net trained from `seed`, with both single-sample steps and parallel batches.
+fn seeded_run(data: &Arc<Vec<NNData>>, seed: u64) -> NeuralNet {
+    let mut nn = NeuralNet::with_structure(vec![12, 16, 10]);
+    nn.set_dropout(0, 0.3);
+    nn.populate_seeded_weights(seed);
+
+    for data_point in &data[..20] {
+        nn.train_one(data_point);
+    }
+
+    let mut trainer = ParallelTrainer::new(3, 8, nn.next_seed());
+    let mut nn = Arc::new(nn);
+    for _ in 0..5 {
+        let batch = trainer.next_batch(data.len());
+        let (gradients, _) = trainer.compute_gradients(&nn, data, &batch);
+        Arc::make_mut(&mut nn).apply_gradients(&gradients);
+    }
+
+    Arc::unwrap_or_clone(nn)
+}
+
+#[test]
+fn test_seeded_runs_are_bit_identical() {
+    let data = Arc::new(generate_learnable_data(64, 12, 48));
+
+    let a = seeded_run(&data, 49);
+    let b = seeded_run(&data, 49);
+    assert_eq!(a.seed(), 49);
+
+    let bits = |nn: &NeuralNet| nn.parameters().concat().iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+    assert_eq!(bits(&a), bits(&b));
+
+    assert_ne!(bits(&a), bits(&seeded_run(&data, 50)));
+}
+
+#[test]
+fn test_evaluate_counts_correct_predictions() {
+    let data = generate_learnable_data(300, 12, 51);
+    let mut nn = generate_net(vec![12, 10], 52);
+
+    let before = nn.evaluate(&data);
+    for _ in 0..5 {
+        for data_point in &data {
+            nn.train_one(data_point);
+        }
+    }
+    let after = nn.evaluate(&data);
+
+    // more than one evaluation batch, and the same as predicting one at a time.
+    let correct = data.iter()
+        .filter(|data_point| {
+            let output = nn.image_to_prediction(scale_and_normalize_data(&data_point.data));
+            argmax(&output) == data_point.label
+        })
+        .count();
+    assert_eq!(after.accuracy, correct as F / data.len() as F);
+
+    assert!(after.loss < before.loss, "{} !< {}", after.loss, before.loss);
+    assert!(after.accuracy > 0.9, "accuracy {}", after.accuracy);
+}
+
+#[test]
+fn test_split_validation() {
+    let data = generate_data(120, 12, 53);
+    let (training, validation) = validation::split_validation(generate_data(120, 12, 53), 0.25, 54);
+    assert_eq!((training.len(), validation.len()), (90, 30));
+
+    // nothing is lost or duplicated, and the same seed splits the same way.
+    let mut all: Vec<Vec<u8>> = training.iter().chain(&validation).map(|d| d.data.clone()).collect();
+    let mut original: Vec<Vec<u8>> = data.iter().map(|d| d.data.clone()).collect();
+    all.sort();
+    original.sort();
+    assert_eq!(all, original);
+
+    let (_, again) = validation::split_validation(data, 0.25, 54);
+    assert!(zip(&validation, &again).all(|(a, b)| a.data == b.data));
+}
+
+#[test]
+fn test_early_stopping_patience_and_min_delta() {
+    let mut nn = generate_net(vec![12, 10], 55);
+    let mut stopper = EarlyStopper::new(EarlyStopping {
+        metric: Metric::ValidationAccuracy,
+        patience: 2,
+        min_delta: 0.01,
+        restore_best_weights: false,
+    });
+
+    let accuracy = |accuracy| Evaluation { loss: 0.0, accuracy };
+    assert_eq!(stopper.update(&mut nn, &accuracy(0.5), 100), None);
+    assert_eq!(stopper.update(&mut nn, &accuracy(0.6), 200), None);
+    // not better by more than min_delta.
+    assert_eq!(stopper.update(&mut nn, &accuracy(0.605), 300), None);
+    // an improvement resets the patience.
+    assert_eq!(stopper.update(&mut nn, &accuracy(0.7), 400), None);
+    assert_eq!(stopper.update(&mut nn, &accuracy(0.65), 500), None);
+
+    let stop = stopper.update(&mut nn, &accuracy(0.69), 600).unwrap();
+    assert_eq!((stop.samples, stop.best_samples, stop.best_value, stop.restored), (600, 400, 0.7, false));
+}
+
+#[test]
+fn test_early_stopping_restores_best_weights() {
+    let mut nn = generate_net(vec![12, 16, 10], 56);
+    nn.set_batch_norm(0, true);
+    let best = nn.clone();
+
+    let mut stopper = EarlyStopper::new(EarlyStopping {
+        metric: Metric::ValidationLoss,
+        patience: 1,
+        min_delta: 0.0,
+        restore_best_weights: true,
+    });
+    let loss = |loss| Evaluation { loss, accuracy: 0.0 };
+    assert_eq!(stopper.update(&mut nn, &loss(1.0), 100), None);
+
+    // training moves both the parameters and the running statistics.
+    for data_point in &generate_learnable_data(20, 12, 57) {
+        nn.train_one(data_point);
+    }
+    assert_ne!(nn.parameters(), best.parameters());
+
+    let stop = stopper.update(&mut nn, &loss(1.5), 200).unwrap();
+    assert!(stop.restored);
+    assert_eq!(nn.parameters(), best.parameters());
+    assert_eq!(nn.statistics(), best.statistics());
+}
+
+// how far one step with `clipping` moves the weights, as (largest change, L2 norm of the change).
+fn clipped_step(clipping: GradientClipping) -> (F, F) {
+    let data = generate_learnable_data(8, 12, 58);
+    let mut nn = generate_net(vec![12, 16, 10], 59);
+    nn.set_learning_rate(1.0);
+    nn.set_gradient_clipping(clipping);
+
+    let mut gradients = nn.zero_gradients();
+    nn.accumulate_gradients(&data.iter().collect::<Vec<_>>(), &mut gradients, &mut StdRng::seed_from_u64(60));
+
+    let before = nn.parameters().concat();
+    nn.apply_gradients(&gradients);
+    let steps: Vec<F> = zip(before, nn.parameters().concat()).map(|(a, b)| b - a).collect();
+
+    (steps.iter().fold(0.0, |max, s| max.max(s.abs())), steps.iter().map(|s| s * s).sum::<F>().sqrt())
+}
+
+#[test]
+fn test_gradient_clipping() {
+    let (max, norm) = clipped_step(GradientClipping::default());
+    assert!(max > 0.1 && norm > 0.5, "max {max}, norm {norm}");
+
+    let (clipped_max, _) = clipped_step(GradientClipping { value: Some(0.05), norm: None });
+    assert!((clipped_max - 0.05).abs() < 1e-6, "max {clipped_max}");
+
+    let (_, clipped_norm) = clipped_step(GradientClipping { value: None, norm: Some(0.2) });
+    assert!((clipped_norm - 0.2).abs() < 1e-4, "norm {clipped_norm}");
+
+    // a limit that isn't reached changes nothing.
+    assert_eq!(clipped_step(GradientClipping { value: None, norm: Some(2.0 * norm) }), (max, norm));
+}
+
+// how far each of two steps with the same gradients moves every weight.
+fn optimizer_steps(optimizer: Optimizer) -> [Vec<F>; 2] {
+    let data = generate_learnable_data(8, 12, 63);
+    let mut nn = generate_net(vec![12, 16, 10], 64);
+    nn.set_learning_rate(0.01);
+    nn.set_optimizer(optimizer);
+
+    let mut gradients = nn.zero_gradients();
+    nn.accumulate_gradients(&data.iter().collect::<Vec<_>>(), &mut gradients, &mut StdRng::seed_from_u64(65));
+
+    [(); 2].map(|_| {
+        let before = nn.parameters().concat();
+        nn.apply_gradients(&gradients);
+        zip(before, nn.parameters().concat()).map(|(a, b)| b - a).collect()
+    })
+}
+
+#[test]
+fn test_optimizers() {
+    // (steps are measured as differences of weights, so they're only equal up to rounding.)
+    let [first, second] = optimizer_steps(Optimizer::Sgd);
+    for (first, second) in zip(first, second) {
+        assert!((second - first).abs() <= 1e-6, "first {first}, second {second}");
+    }
+
+    // the second step adds `beta` of the first onto itself.
+    let [first, second] = optimizer_steps(Optimizer::Momentum { beta: 0.9 });
+    for (first, second) in zip(first, second) {
+        assert!((second - 1.9 * first).abs() <= 1e-6, "first {first}, second {second}");
+    }
+
+    // Adam's steps are about the learning rate, whatever the size of the gradient.
+    let [first, second] = optimizer_steps(Optimizer::Adam { beta1: 0.9, beta2: 0.999, epsilon: 1e-8 });
+    let moved: Vec<F> = first.iter().chain(&second).map(|step| step.abs()).filter(|&step| step > 1e-4).collect();
+    assert!(moved.len() > first.len());
+    assert!(moved.iter().all(|step| (step - 0.01).abs() < 1e-3), "{moved:?}");
+}
+
+#[test]
+fn test_structure() {
+    let mut nn = NeuralNet::with_structure(vec![12, 16, 8, 10]);
+    nn.set_dropout(0, 0.5);
+    nn.set_batch_norm(1, true);
+    assert_eq!(nn.structure(), Some(vec![12, 16, 8, 10]));
+
+    assert_eq!(NeuralNet::lenet().structure(), None);
+}
+
+#[test]
+fn test_attribution_gradient_matches_finite_differences() {
+    let nn = generate_net(vec![12, 16, 10], 67);
+    let input = scale_and_normalize_data(&generate_data(1, 12, 68)[0].data);
+    let output = |input: &[F]| nn.image_to_prediction(input.to_vec())[3];
+
+    let gradient = nn.attribution(&input, 3, Attribution::Gradient);
+    for i in 0..input.len() {
+        let (mut up, mut down) = (input.clone(), input.clone());
+        up[i] += 0.01;
+        down[i] -= 0.01;
+        let numerical = (output(&up) - output(&down)) / 0.02;
+        assert!((gradient[i] - numerical).abs() < 1e-3, "input {i}: {} vs {numerical}", gradient[i]);
+    }
+
+    let times_input = nn.attribution(&input, 3, Attribution::GradientTimesInput);
+    for ((credit, g), x) in zip(zip(times_input, gradient), &input) {
+        assert!((credit - g * x).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn test_integrated_gradients_add_up_to_the_change_from_blank() {
+    let nn = generate_net(vec![12, 16, 10], 69);
+    let input = scale_and_normalize_data(&generate_data(1, 12, 70)[0].data);
+    let blank = scale_and_normalize_data(&[0; 12]);
+
+    let credits = nn.attribution(&input, 7, Attribution::IntegratedGradients { steps: 256 });
+    let change = nn.image_to_prediction(input.clone())[7] - nn.image_to_prediction(blank)[7];
+    let total: F = credits.iter().sum();
+    assert!((total - change).abs() < 1e-3, "credits add up to {total}, the output changed by {change}");
+}
+
+#[test]
+fn test_attacks_stay_in_the_epsilon_ball_and_raise_the_loss() {
+    let data_point = &generate_learnable_data(1, 12, 71)[0];
+    let nn = generate_net(vec![12, 16, 10], 72);
+    let original = scale_and_normalize_data(&data_point.data);
+    let loss = |input: Vec<F>| {
+        let mut cache = nn.forward(Matrix::from_values(input, 1, 12), None);
+        let loss = cache.record(|tape, output| nn.record_loss(tape, output, batch_targets(&[data_point])));
+        cache.value(loss).get_raw_slice()[0]
+    };
+
+    for attack in [Attack::Fgsm { epsilon: 0.05 }, Attack::Pgd { epsilon: 0.05, step_size: 0.01, steps: 10 }] {
+        let adversarial = nn.attack(data_point, attack);
+        for (x, original) in zip(&adversarial, &original) {
+            assert!((x - original).abs() <= 0.05 + 1e-6, "{attack:?}: {x} is too far from {original}");
+            assert!((0.01..=0.99).contains(x), "{attack:?}: {x} isn't a pixel value");
+        }
+        assert!(loss(adversarial) > loss(original.clone()), "{attack:?}");
+    }
+}
+
+#[test]
+fn test_attack_success_rate() {
+    let data = generate_learnable_data(300, 12, 73);
+    let mut nn = generate_net(vec![12, 10], 74);
+    for _ in 0..5 {
+        for data_point in &data {
+            nn.train_one(data_point);
+        }
+    }
+
+    // without any room to move, nothing changes.
+    let report = nn.attack_success_rate(&data, Attack::Fgsm { epsilon: 0.0 });
+    assert_eq!(report.samples, 300);
+    assert_eq!(report.correct as F / 300.0, nn.evaluate(&data).accuracy);
+    assert_eq!(report.fooled, 0);
+
+    // enough to dim the brightest pixel and light up another.
+    let strong = nn.attack_success_rate(&data, Attack::Pgd { epsilon: 0.5, step_size: 0.05, steps: 20 });
+    assert!(strong.success_rate() > 0.5, "{strong}");
+}
+
+#[test]
+fn test_find_non_finite_names_the_layer() {
+    let probe = &generate_data(1, 12, 61)[0];
+    let mut nn = generate_net(vec![12, 16, 10], 62);
+    assert_eq!(nn.find_non_finite(&[0.5, 1.2], probe), None);
+    assert_eq!(nn.find_non_finite(&[0.5, F::NAN], probe), Some(NonFinite::Loss));
+
+    // finite weights can still overflow the outputs.
+    let mut overflowing = nn.clone();
+    overflowing.layers.layers_mut()[0].parameters_mut()[0][..12].fill(F::MAX);
+    assert_eq!(overflowing.find_non_finite(&[0.5], probe), Some(NonFinite::Activations(0, "Dense 12 → 16".to_string())));
+
+    nn.layers.layers_mut()[2].parameters_mut()[0][3] = F::NAN;
+    assert_eq!(nn.find_non_finite(&[0.5], probe), Some(NonFinite::Weights(2, "Dense 16 → 10".to_string())));
+}
+
+#[test]
+fn test_history_stays_bounded() {
+    let values: Vec<F> = (0..100_000).map(|i| ((i * 7919) % 1000) as F).collect();
+    let mut history = History::new(64);
+    history.extend(values.iter().copied());
+    assert_eq!(history.len(), values.len());
+
+    // the whole run, in few enough buckets, which still sum up every value.
+    let buckets = history.buckets(0..values.len(), 64);
+    assert!(buckets.len() < 64, "{} buckets", buckets.len());
+    assert_eq!(buckets.iter().map(|bucket| bucket.count).sum::<usize>(), values.len());
+    for pair in buckets.windows(2) {
+        assert_eq!(pair[0].end(), pair[1].start);
+    }
+
+    let mean = buckets.iter().map(|bucket| bucket.mean() as f64 * bucket.count as f64).sum::<f64>() / values.len() as f64;
+    let expected = values.iter().map(|&x| x as f64).sum::<f64>() / values.len() as f64;
+    assert!((mean - expected).abs() < 1e-3, "{mean} != {expected}");
+    assert_eq!(buckets.iter().fold(F::MAX, |min, bucket| min.min(bucket.min)), 0.0);
+    assert_eq!(buckets.iter().fold(F::MIN, |max, bucket| max.max(bucket.max)), 999.0);
+
+    // the latest values are still there one by one.
+    let latest = history.buckets(values.len() - 10..values.len(), 64);
+    assert_eq!(latest.iter().map(|bucket| bucket.mean()).collect::<Vec<_>>(), values[values.len() - 10..]);
+
+    // a stretch from long ago comes from a coarser level, but covers what was asked for.
+    let old = history.buckets(1000..1100, 64);
+    assert!(old.first().unwrap().start <= 1000 && old.last().unwrap().end() >= 1100);
+    assert!(old.iter().all(|bucket| bucket.count > 1));
+}
+
+#[test]
+fn test_zoomed_in_smoothing_carries_on_from_before() {
+    let values: Vec<F> = (0..500).map(|i| ((i * 7919) % 1000) as F / (1.0 + i as F)).collect();
+    let mut history = History::new(1000);
+    history.extend(values.iter().copied());
+
+    let whole = history.buckets(0..values.len(), 1000);
+    let zoomed = history.buckets(300..400, 1000);
+    assert_eq!(whole.len(), values.len());
+    assert_eq!(zoomed.first().unwrap().start, 300);
+
+    let whole_average = history.exponential_moving_average(&whole, 0.9);
+    let zoomed_average = history.exponential_moving_average(&zoomed, 0.9);
+    for (x, y) in zip(&whole_average[300..400], &zoomed_average) {
+        assert!((x - y).abs() < 1e-4, "{x} != {y}");
+    }
+
+    // the first value starts it off.
+    assert_eq!(whole_average[0], values[0]);
+}
+
+// one of 10 fixed 4×4 patterns, at a random place on a noisy 12×12 image; the label is
+// which pattern it is. A net has to find the pattern wherever it is, like a digit that
+// isn't centred.
+fn generate_shifted_patterns(count: usize, seed: u64) -> Vec<NNData> {
+    let mut patterns_rng = StdRng::seed_from_u64(0);
+    let patterns: Vec<Vec<bool>> = (0..10).map(|_| (0..16).map(|_| patterns_rng.random_bool(0.5)).collect()).collect();
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..count)
+        .map(|_| {
+            let label = rng.random_range(0..10);
+            let mut data: Vec<u8> = (0..12 * 12).map(|_| rng.random_range(0..32)).collect();
+            let (top, left) = (rng.random_range(0..=8), rng.random_range(0..=8));
+            for (i, &on) in patterns[label].iter().enumerate() {
+                if on {
+                    data[(top + i / 4) * 12 + left + i % 4] = 255;
+                }
+            }
+            NNData { data, label }
+        })
+        .collect()
+}
+
+#[test]
+fn test_conv_net_beats_dense_on_shifted_patterns() {
+    let training = generate_shifted_patterns(500, 60);
+    let testing = generate_shifted_patterns(200, 61);
+
+    // the LeNet preset scaled down to the images (it takes too long in a debug build):
+    // one convolution + max pooling stage, then a dense layer, trained the same way.
+    let conv = Conv2D::new(Shape::new(1, 12, 12), 8, 4, 1, 0);
+    let pool = MaxPool2D::new(conv.output_shape(), 9, 9);
+    let features = pool.output_shape().size();
+
+    let mut layers = Sequential::new();
+    layers.push(conv);
+    layers.push(Activation::Relu);
+    layers.push(pool);
+    layers.push(Dense::new(features, 10));
+    layers.push(Activation::Softmax);
+
+    let mut conv_net = NeuralNet::from_layers(layers);
+    conv_net.set_loss(Loss::CrossEntropy);
+    conv_net.set_learning_rate(0.02);
+    conv_net.set_initialization(Initialization::HeNormal);
+
+    // and the default dense net, with an input layer the size of the images.
+    let dense = NeuralNet::with_structure(vec![12 * 12, 160, 10]);
+
+    let accuracy = |mut nn: NeuralNet| {
+        nn.populate_seeded_weights(62);
+        for _ in 0..2 {
+            for data_point in &training {
+                nn.train_one(data_point);
+            }
+        }
+        nn.evaluate(&testing).accuracy
+    };
+
+    let dense = accuracy(dense);
+    let conv_net = accuracy(conv_net);
+    assert!(conv_net > 0.8, "conv net: {conv_net}");
+    assert!(conv_net > dense + 0.3, "dense: {dense}, conv net: {conv_net}");
+}
@@ -0,0 +1,216 @@
+use super::{ NeuralNet, NNData, argmax, batch_inputs, batch_targets };
+use super::math::F;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+
+use std::fmt;
+use std::iter::zip;
+
+// how many samples are fed through the net at once when evaluating.
+const EVALUATION_BATCH_SIZE: usize = 256;
+
+/// How a net does on data it isn't trained on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Evaluation {
+    /// Mean loss per sample, without the regularization penalty.
+    pub loss: F,
+    /// Fraction of samples whose largest output is their label.
+    pub accuracy: F,
+}
+
+/// What early stopping watches.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    ValidationLoss,
+    ValidationAccuracy,
+}
+
+/// When to give up on training because the validation metric stopped improving.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EarlyStopping {
+    pub metric: Metric,
+    /// How many evaluations in a row may fail to improve on the best before stopping.
+    pub patience: usize,
+    /// The smallest change in the metric that counts as an improvement.
+    pub min_delta: F,
+    /// Go back to the weights from the best evaluation when stopping.
+    pub restore_best_weights: bool,
+}
+
+/// Why and when training was stopped early.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EarlyStop {
+    pub policy: EarlyStopping,
+    /// Training samples seen when training stopped.
+    pub samples: usize,
+    /// Training samples seen at the best evaluation.
+    pub best_samples: usize,
+    pub best_value: F,
+    /// Whether the weights from the best evaluation were put back.
+    pub restored: bool,
+}
+
+/// Follows an `EarlyStopping` policy over the evaluations of one training run.
+#[derive(Debug)]
+pub struct EarlyStopper {
+    policy: EarlyStopping,
+    // value and samples seen at the best evaluation so far.
+    best: Option<(F, usize)>,
+    best_weights: Option<NeuralNet>,
+    evaluations_without_improvement: usize,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 2] = [
+        Metric::ValidationLoss,
+        Metric::ValidationAccuracy,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::ValidationLoss => "validation loss",
+            Metric::ValidationAccuracy => "validation accuracy",
+        }
+    }
+
+    pub fn value(&self, evaluation: &Evaluation) -> F {
+        match self {
+            Metric::ValidationLoss => evaluation.loss,
+            Metric::ValidationAccuracy => evaluation.accuracy,
+        }
+    }
+
+    // how much better `value` is than `best`; loss should go down, accuracy up.
+    fn improvement(&self, best: F, value: F) -> F {
+        match self {
+            Metric::ValidationLoss => best - value,
+            Metric::ValidationAccuracy => value - best,
+        }
+    }
+}
+
+impl Default for EarlyStopping {
+    fn default() -> Self {
+        Self {
+            metric: Metric::ValidationLoss,
+            patience: 5,
+            min_delta: 0.001,
+            restore_best_weights: true,
+        }
+    }
+}
+
+impl fmt::Display for EarlyStop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "stopped after {} samples: {} didn't improve by {} for {} evaluations; best was {:.4} after {} samples",
+            self.samples, self.policy.metric.name(), self.policy.min_delta, self.policy.patience, self.best_value, self.best_samples)?;
+
+        if self.restored {
+            write!(f, " (weights restored)")?;
+        }
+        Ok(())
+    }
+}
+
+impl EarlyStopper {
+    pub fn new(policy: EarlyStopping) -> Self {
+        Self {
+            policy,
+            best: None,
+            best_weights: None,
+            evaluations_without_improvement: 0,
+        }
+    }
+
+    /// Record an evaluation of `nn`, made after `samples` training samples. If it's time to
+    /// stop, says why, after putting the best weights back into `nn` if the policy asks for it.
+    pub fn update(&mut self, nn: &mut NeuralNet, evaluation: &Evaluation, samples: usize) -> Option<EarlyStop> {
+        let value = self.policy.metric.value(evaluation);
+
+        let improved = self.best.is_none_or(|(best, _)| self.policy.metric.improvement(best, value) > self.policy.min_delta);
+        if improved {
+            self.best = Some((value, samples));
+            self.evaluations_without_improvement = 0;
+            if self.policy.restore_best_weights {
+                self.best_weights = Some(nn.clone());
+            }
+            return None;
+        }
+
+        self.evaluations_without_improvement += 1;
+        if self.evaluations_without_improvement < self.policy.patience {
+            return None;
+        }
+
+        let restored = self.best_weights.as_ref().is_some_and(|best| nn.restore_weights(best));
+        let (best_value, best_samples) = self.best.unwrap();
+
+        Some(EarlyStop {
+            policy: self.policy,
+            samples,
+            best_samples,
+            best_value,
+            restored,
+        })
+    }
+}
+
+impl NeuralNet {
+    /// Loss and accuracy over `data`, as predicted (without dropout or batch statistics).
+    /// `data` mustn't be empty.
+    pub fn evaluate(&self, data: &[NNData]) -> Evaluation {
+        assert!(!data.is_empty(), "can't evaluate on no data");
+
+        let mut loss = 0.0;
+        let mut correct = 0;
+
+        for chunk in data.chunks(EVALUATION_BATCH_SIZE) {
+            let data_points: Vec<&NNData> = chunk.iter().collect();
+            let mut cache = self.forward(batch_inputs(&data_points), None);
+            let losses = cache.record(|tape, output| self.record_loss(tape, output, batch_targets(&data_points)));
+            loss += cache.value(losses).get_raw_slice().iter().sum::<F>();
+
+            for (output, data_point) in zip(cache.output().iter_row_slices(), chunk) {
+                if argmax(output) == data_point.label {
+                    correct += 1;
+                }
+            }
+        }
+
+        Evaluation {
+            loss: loss / data.len() as F,
+            accuracy: correct as F / data.len() as F,
+        }
+    }
+
+    // copies the parameters and statistics of `other`, if it has the same layers; returns
+    // whether it did.
+    fn restore_weights(&mut self, other: &NeuralNet) -> bool {
+        let same_shape = |a: Vec<&[F]>, b: Vec<&[F]>| a.len() == b.len() && zip(&a, &b).all(|(a, b)| a.len() == b.len());
+        if !same_shape(self.parameters(), other.parameters()) || !same_shape(self.statistics(), other.statistics()) {
+            return false;
+        }
+
+        for (values, other) in zip(self.parameters_mut(), other.parameters()) {
+            values.copy_from_slice(other);
+        }
+        for (values, other) in zip(self.statistics_mut(), other.statistics()) {
+            values.copy_from_slice(other);
+        }
+        true
+    }
+}
+
+/// Shuffles `data` (the same way for the same seed) and splits off `fraction` of it for
+/// validation. Returns `(training, validation)`.
+pub fn split_validation(mut data: Vec<NNData>, fraction: F, seed: u64) -> (Vec<NNData>, Vec<NNData>) {
+    assert!((0.0..1.0).contains(&fraction));
+
+    data.shuffle(&mut StdRng::seed_from_u64(seed));
+    let validation_size = (data.len() as F * fraction).round() as usize;
+    let validation = data.split_off(data.len() - validation_size);
+
+    (data, validation)
+}
@@ -0,0 +1,101 @@
+use eframe::egui::{ self, Color32, ColorImage, TextureHandle, TextureOptions };
+
+use crate::heatmap;
+use crate::neural_net::{ self, NeuralNet };
+use crate::neural_net::saliency::Attribution;
+
+const IMAGE_SIZE: [usize; 2] = [28, 28];
+
+// how opaque the pixels with the most credit are.
+const MAX_ALPHA: f32 = 0.8;
+
+/// Colours each pixel of an image by the credit it gets for one of the net's outputs, drawn
+/// over the image: red pixels pushed the output up, blue ones down, and pixels without much
+/// credit either way are left clear.
+#[derive(Default)]
+pub struct SaliencyOverlay {
+    /// `None` draws nothing.
+    method: Option<Attribution>,
+    /// The output explained; `None` is whichever the net predicts.
+    class: Option<usize>,
+    texture: Option<TextureHandle>,
+    // what `texture` shows, so it's only worked out again when that changes.
+    drawn: Option<Drawn>,
+}
+
+#[derive(PartialEq)]
+struct Drawn {
+    input: Vec<f32>,
+    class: Option<usize>,
+    method: Attribution,
+    nn_version: u64,
+}
+
+impl SaliencyOverlay {
+    pub fn controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal_top(|ui| {
+            egui::ComboBox::from_label("Saliency")
+                .selected_text(self.method.map_or("Off", |method| method.name()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.method, None, "Off");
+                    for method in Attribution::ALL {
+                        ui.selectable_value(&mut self.method, Some(method), method.name());
+                    }
+                });
+
+            if self.method.is_some() {
+                egui::ComboBox::from_label("Explaining")
+                    .selected_text(self.class.map_or("Prediction".to_string(), |class| class.to_string()))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.class, None, "Prediction");
+                        for class in 0..10 {
+                            ui.selectable_value(&mut self.class, Some(class), class.to_string());
+                        }
+                    });
+            }
+        });
+    }
+
+    /// Paints the overlay for `input` over `rect`, where the image is drawn. `nn_version`
+    /// changes whenever `nn` does.
+    pub fn paint(&mut self, ui: &egui::Ui, rect: egui::Rect, nn: &NeuralNet, nn_version: u64, input: &[f32]) {
+        let Some(method) = self.method else {
+            return;
+        };
+        if input.len() != IMAGE_SIZE[0] * IMAGE_SIZE[1] {
+            return;
+        }
+
+        let drawn = Drawn { input: input.to_vec(), class: self.class, method, nn_version };
+        if self.drawn.as_ref() != Some(&drawn) {
+            self.draw(ui, nn, &drawn);
+            self.drawn = Some(drawn);
+        }
+
+        if let Some(texture) = &self.texture {
+            let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+            ui.painter().image(texture.id(), rect, uv, Color32::WHITE);
+        }
+    }
+
+    fn draw(&mut self, ui: &egui::Ui, nn: &NeuralNet, drawn: &Drawn) {
+        let (input, method) = (&drawn.input, drawn.method);
+        let class = drawn.class.unwrap_or_else(|| neural_net::argmax(&nn.image_to_prediction(input.to_vec())));
+
+        let credits = nn.attribution(input, class, method);
+        let largest = credits.iter().fold(f32::MIN_POSITIVE, |largest, credit| largest.max(credit.abs()));
+        let pixels = credits.iter()
+            .map(|credit| {
+                let value = credit / largest;
+                let [r, g, b, _] = heatmap::diverging_color(value).to_array();
+                Color32::from_rgba_unmultiplied(r, g, b, (255.0 * MAX_ALPHA * value.abs()) as u8)
+            })
+            .collect();
+        let image = ColorImage { size: IMAGE_SIZE, pixels };
+
+        match &mut self.texture {
+            Some(texture) => texture.set(image, TextureOptions::NEAREST),
+            None => self.texture = Some(ui.ctx().load_texture("saliency", image, TextureOptions::NEAREST)),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use eframe::egui;
+use egui_plot::{ AxisHints, Corner, HPlacement, Legend, Line, Plot, PlotPoints };
+
+use crate::neural_net::validation::Evaluation;
+use crate::neural_net::history::{ Bucket, History };
+
+use std::iter::zip;
+
+// more points than pixels across the plot don't show anything more.
+const MAX_PLOTTED_POINTS: usize = 2000;
+
+// log scale can't show 0; anything smaller is drawn as this.
+const LOG_FLOOR: f64 = 1e-6;
+
+const ACCURACY_NAME: &str = "validation accuracy";
+
+/// What the x-axis of the Train view's plot counts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XAxis {
+    Samples,
+    Epochs,
+}
+
+/// How the Train view plots the training loss and the validation loss and accuracy.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrainPlot {
+    /// How much of the running average each training loss keeps; 0 plots the raw losses.
+    pub smoothing: f64,
+    /// Also draw the smallest and largest training loss around each plotted point.
+    pub show_range: bool,
+    pub log_scale: bool,
+    pub x_axis: XAxis,
+}
+
+impl Default for TrainPlot {
+    fn default() -> Self {
+        Self {
+            smoothing: 0.99,
+            show_range: false,
+            log_scale: false,
+            x_axis: XAxis::Samples,
+        }
+    }
+}
+
+impl TrainPlot {
+    pub fn controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal_top(|ui| {
+            ui.add(egui::Slider::new(&mut self.smoothing, 0.0..=0.999).text("Smoothing"));
+            ui.checkbox(&mut self.show_range, "Min/Max Loss");
+            ui.checkbox(&mut self.log_scale, "Log Scale");
+            ui.label("x-axis:");
+            ui.selectable_value(&mut self.x_axis, XAxis::Samples, "Samples");
+            ui.selectable_value(&mut self.x_axis, XAxis::Epochs, "Epochs");
+        });
+    }
+
+    /// `losses` has the training loss of every sample trained on, and `validation` the
+    /// evaluations made along the way, by the number of samples trained on before them.
+    pub fn show(&self, ui: &mut egui::Ui, losses: &History, validation: &[(usize, Evaluation)], epoch_size: usize) {
+        let samples_per_x = match self.x_axis {
+            XAxis::Samples => 1.0,
+            XAxis::Epochs => epoch_size.max(1) as f64,
+        };
+        let x = move |samples: f64| samples / samples_per_x;
+        let log_scale = self.log_scale;
+        let y = move |value: f32| if log_scale { (value as f64).max(LOG_FLOOR).log10() } else { value as f64 };
+
+        let (show_range, smoothing) = (self.show_range, self.smoothing);
+        let lines = move |buckets: &[Bucket]| -> [Vec<[f64; 2]>; 3] {
+            let middle = |bucket: &Bucket| x(bucket.start as f64 + bucket.count as f64 / 2.0);
+            let smoothed = zip(buckets, losses.exponential_moving_average(buckets, smoothing))
+                .map(|(bucket, mean)| [middle(bucket), y(mean)])
+                .collect();
+            let bound = |value: fn(&Bucket) -> f32| if show_range {
+                buckets.iter().map(|bucket| [middle(bucket), y(value(bucket))]).collect()
+            } else {
+                Vec::new()
+            };
+            [smoothed, bound(|bucket| bucket.min), bound(|bucket| bucket.max)]
+        };
+
+        let validation_loss: Vec<[f64; 2]> = validation.iter()
+            .map(|(samples, evaluation)| [x(*samples as f64), y(evaluation.loss)])
+            .collect();
+
+        // accuracy gets the right axis, which spans the same height as the whole run's losses
+        // on the left.
+        let overview = lines(&losses.buckets(0..losses.len(), MAX_PLOTTED_POINTS));
+        let (mut low, mut high) = overview.iter().flatten().chain(&validation_loss)
+            .fold((f64::MAX, f64::MIN), |(low, high), [_, y]| (low.min(*y), high.max(*y)));
+        if !log_scale {
+            low = low.min(0.0);
+        }
+        if low >= high {
+            (low, high) = if low == f64::MAX { (0.0, 1.0) } else { (low, low + 1.0) };
+        }
+        let to_accuracy = move |y: f64| (y - low) / (high - low);
+
+        let validation_accuracy: Vec<[f64; 2]> = validation.iter()
+            .map(|(samples, evaluation)| [x(*samples as f64), low + evaluation.accuracy as f64 * (high - low)])
+            .collect();
+
+        let value_text = move |y: f64| if log_scale { format!("{:.3}", 10f64.powf(y)) } else { format!("{y:.3}") };
+        let x_name = match self.x_axis {
+            XAxis::Samples => "samples",
+            XAxis::Epochs => "epochs",
+        };
+
+        // each scale keeps its own bounds; the other scale's would be way off.
+        Plot::new(("train_plot", log_scale))
+            .view_aspect(2.0)
+            .legend(Legend::default().position(Corner::RightTop))
+            .x_axis_label(x_name)
+            .custom_y_axes(vec![
+                AxisHints::new_y()
+                    .label("training / validation loss")
+                    .formatter(move |mark, _| value_text(mark.value)),
+                AxisHints::new_y()
+                    .label(ACCURACY_NAME)
+                    .placement(HPlacement::Right)
+                    .formatter(move |mark, _| format!("{:.0}%", 100.0 * to_accuracy(mark.value))),
+            ])
+            .label_formatter(move |name, point| match name {
+                "" => String::new(),
+                ACCURACY_NAME => format!("{name}\n{:.2}% after {:.2} {x_name}", 100.0 * to_accuracy(point.y), point.x),
+                _ => format!("{name}\n{} after {:.2} {x_name}", value_text(point.y), point.x),
+            })
+            .show(ui, |plot_ui| {
+                // zoomed in, the part in view is drawn in as much detail as the history has.
+                let [smoothed, min, max] = if plot_ui.auto_bounds().x {
+                    overview
+                } else {
+                    let bounds = plot_ui.plot_bounds();
+                    let sample = |x: f64| (x * samples_per_x).max(0.0) as usize;
+                    lines(&losses.buckets(sample(bounds.min()[0])..sample(bounds.max()[0]) + 1, MAX_PLOTTED_POINTS))
+                };
+
+                if show_range {
+                    plot_ui.line(Line::new(PlotPoints::new(min)).name("training loss (min)").width(0.5));
+                    plot_ui.line(Line::new(PlotPoints::new(max)).name("training loss (max)").width(0.5));
+                }
+                plot_ui.line(Line::new(PlotPoints::new(smoothed)).name("training loss (smoothed)"));
+                plot_ui.line(Line::new(PlotPoints::new(validation_loss)).name("validation loss"));
+                plot_ui.line(Line::new(PlotPoints::new(validation_accuracy)).name(ACCURACY_NAME));
+            });
+    }
+}
@@ -0,0 +1,634 @@
+#[cfg(test)]
+mod tests;
+
+use eframe::egui;
+
+use crate::neural_net::{ NeuralNet, NNData };
+use crate::neural_net::parallel::ParallelTrainer;
+use crate::neural_net::hogwild::HogwildTrainer;
+use crate::neural_net::activation::Activation;
+use crate::neural_net::loss::Loss;
+use crate::neural_net::init::Initialization;
+use crate::neural_net::regularization::Regularization;
+use crate::neural_net::clipping::GradientClipping;
+use crate::neural_net::optimizer::Optimizer;
+use crate::neural_net::health::NonFinite;
+use crate::neural_net::validation::{ Evaluation, EarlyStopping, EarlyStopper, EarlyStop };
+
+use rand::{ Rng, SeedableRng };
+use rand::rngs::StdRng;
+
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+use std::sync::mpsc::{ self, Receiver, Sender, TryRecvError };
+use std::thread::{ self, JoinHandle };
+use std::time::{ Duration, Instant };
+
+// how often the GUI gets a copy of the weights being trained.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_millis(100);
+
+// training losses are checked and sent on in batches of at least this many.
+const LOSSES_PER_REPORT: usize = 200;
+
+// how often the hogwild workers are reported on.
+const HOGWILD_REPORT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The settings the Train view has for the net itself, which also apply while it's training.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NetSettings {
+    pub learning_rate: f32,
+    pub regularization: Regularization,
+    pub gradient_clipping: GradientClipping,
+    pub optimizer: Optimizer,
+    pub hidden_activation: Activation,
+    pub output_activation: Activation,
+    pub loss: Loss,
+    pub dropout: f32,
+    pub batch_norm: bool,
+    /// Only used when the weights are (re)drawn.
+    pub initialization: Initialization,
+}
+
+/// What the GUI tells the training thread.
+#[derive(Debug)]
+pub enum Command {
+    Stop,
+    Pause,
+    /// Train until paused or stopped.
+    Resume,
+    /// Train this many more samples, then pause. Hogwild workers only report every few
+    /// hundred samples, so they can go over.
+    Train(usize),
+    Settings(NetSettings),
+}
+
+/// What a `Training` is doing; `Idle` is for when there isn't one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrainingState {
+    Idle,
+    Running,
+    /// The thread is waiting for a command, with the weights as they were when it paused.
+    Paused,
+    /// Asked to stop, but it hasn't said it has yet.
+    Stopping,
+}
+
+/// What the training thread tells the GUI.
+#[derive(Debug)]
+pub enum Message {
+    /// The training losses of the samples since the last `Losses`, in order.
+    Losses(Vec<f32>),
+    /// An evaluation on the validation split, after this many samples.
+    Validation(usize, Evaluation),
+    /// A copy of the net being trained, as it is now.
+    Snapshot(Box<NeuralNet>),
+    /// The thread started or stopped training; sent after handling each command that does.
+    State(TrainingState),
+    /// NaN or Inf turned up, so training paused with the weights of the last check without any.
+    NumericalError(NonFinite),
+    /// Training is over; the last snapshot is the final net.
+    Finished(StopReason),
+}
+
+/// Why training ended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    Stopped,
+    EarlyStop(EarlyStop),
+    WorkersExited,
+}
+
+/// How to train; fixed once training has started.
+#[derive(Debug, Clone, Copy)]
+pub struct TrainingOptions {
+    pub threads: usize,
+    pub batch_size: usize,
+    pub hogwild: bool,
+    /// Seeds the trainer's batch sampling and dropout; see `NeuralNet::next_seed`.
+    pub seed: u64,
+    /// How many samples to train on between evaluations on the validation split.
+    pub validation_interval: usize,
+    pub early_stopping: Option<EarlyStopping>,
+    /// Samples trained on in earlier runs; counting carries on from there, like the loss plot.
+    pub samples: usize,
+    /// How many samples to train on before pausing; `None` trains until paused or stopped.
+    pub budget: Option<usize>,
+}
+
+/// A net being trained on its own thread. The GUI never waits on it: it sends `Command`s and
+/// picks up whatever `Message`s have arrived each frame, including copies of the weights to
+/// predict with.
+///
+/// There's only ever one thread per `Training`, and dropping it stops the thread.
+#[derive(Debug)]
+pub struct Training {
+    commands: Sender<Command>,
+    messages: Receiver<Message>,
+    thread: JoinHandle<()>,
+    settings: NetSettings,
+    state: TrainingState,
+}
+
+impl Default for NetSettings {
+    fn default() -> Self {
+        Self {
+            learning_rate: 0.1,
+            regularization: Regularization::default(),
+            gradient_clipping: GradientClipping::default(),
+            optimizer: Optimizer::Sgd,
+            hidden_activation: Activation::Sigmoid,
+            output_activation: Activation::Sigmoid,
+            loss: Loss::SquaredError,
+            dropout: 0.0,
+            batch_norm: false,
+            initialization: Initialization::LeCunNormal,
+        }
+    }
+}
+
+impl NetSettings {
+    pub fn apply(&self, nn: &mut NeuralNet) {
+        nn.set_learning_rate(self.learning_rate);
+        nn.set_regularization(self.regularization);
+        nn.set_gradient_clipping(self.gradient_clipping);
+        nn.set_optimizer(self.optimizer);
+
+        let output_layer = nn.num_dense_layers() - 1;
+        for layer in 0..output_layer {
+            nn.set_activation(layer, self.hidden_activation);
+            nn.set_dropout(layer, self.dropout);
+            nn.set_batch_norm(layer, self.batch_norm);
+        }
+        nn.set_activation(output_layer, self.output_activation);
+        nn.set_loss(self.loss);
+        nn.set_initialization(self.initialization);
+    }
+
+    // whether applying `self` after `other` changes the net's layers (or the loss), rather
+    // than just how it's trained.
+    fn changes_layers(&self, other: &NetSettings) -> bool {
+        self.hidden_activation != other.hidden_activation
+            || self.output_activation != other.output_activation
+            || self.loss != other.loss
+            || self.dropout != other.dropout
+            || self.batch_norm != other.batch_norm
+    }
+
+    /// Take on the settings of `nn`, so applying them doesn't change it.
+    pub fn read_from(&mut self, nn: &NeuralNet) {
+        let output_layer = nn.num_dense_layers() - 1;
+
+        self.learning_rate = nn.learning_rate();
+        self.regularization = nn.regularization();
+        self.gradient_clipping = nn.gradient_clipping();
+        self.optimizer = nn.optimizer();
+        self.loss = nn.loss_function();
+        self.initialization = nn.initialization();
+
+        if let Some(activation) = nn.activation(output_layer) {
+            self.output_activation = activation;
+        }
+
+        if output_layer > 0 {
+            if let Some(activation) = nn.activation(0) {
+                self.hidden_activation = activation;
+            }
+            self.dropout = nn.dropout(0);
+            self.batch_norm = nn.has_batch_norm(0);
+        }
+    }
+}
+
+impl TrainingState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TrainingState::Idle => "idle",
+            TrainingState::Running => "running",
+            TrainingState::Paused => "paused",
+            TrainingState::Stopping => "stopping",
+        }
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Stopped => write!(f, "stopped"),
+            StopReason::EarlyStop(stop) => write!(f, "{stop}"),
+            StopReason::WorkersExited => write!(f, "the hogwild workers exited unexpectedly"),
+        }
+    }
+}
+
+impl Training {
+    /// Trains `nn` (with `settings` applied) on a new thread until it's told to stop, or
+    /// stops itself.
+    pub fn start(
+        mut nn: NeuralNet,
+        settings: NetSettings,
+        training_data: Arc<Vec<NNData>>,
+        validation_data: Arc<Vec<NNData>>,
+        options: TrainingOptions,
+        ctx: Arc<egui::Context>,
+    ) -> Self {
+        let (commands, commands_rx) = mpsc::channel();
+        let (messages_tx, messages) = mpsc::channel();
+
+        settings.apply(&mut nn);
+
+        let thread = thread::spawn(move || {
+            let mut run = Run {
+                commands: commands_rx,
+                budget: options.budget,
+                messages: messages_tx,
+                ctx,
+                last_snapshot: Instant::now(),
+                samples: options.samples,
+                probe_data: if validation_data.is_empty() { Arc::clone(&training_data) } else { Arc::clone(&validation_data) },
+                validation_data,
+                validation_interval: options.validation_interval,
+                next_validation: options.samples + options.validation_interval,
+                stopper: options.early_stopping.map(EarlyStopper::new),
+                last_healthy: nn.clone(),
+            };
+
+            let reason = if options.hogwild {
+                train_hogwild(&mut nn, settings, training_data, &options, &mut run)
+            } else {
+                train_parallel(&mut nn, training_data, &options, &mut run)
+            };
+
+            run.send(Message::Snapshot(Box::new(nn)));
+            run.send(Message::Finished(reason));
+        });
+
+        Self {
+            commands,
+            messages,
+            thread,
+            settings,
+            state: TrainingState::Running,
+        }
+    }
+
+    pub fn state(&self) -> TrainingState {
+        self.state
+    }
+
+    // if the thread is gone, it's stopped already, and `Message::Finished` says why.
+    fn send(&self, command: Command) {
+        let _ = self.commands.send(command);
+    }
+
+    /// Asks training to stop; it says so with `Message::Finished` once it has.
+    pub fn stop(&mut self) {
+        self.send(Command::Stop);
+        self.state = TrainingState::Stopping;
+    }
+
+    pub fn pause(&self) {
+        self.send(Command::Pause);
+    }
+
+    // running straight away, so the GUI doesn't offer to start it twice before the thread says so.
+    pub fn resume(&mut self) {
+        self.send(Command::Resume);
+        self.state = TrainingState::Running;
+    }
+
+    /// Train on `samples` more samples, then pause again.
+    pub fn train(&mut self, samples: usize) {
+        self.send(Command::Train(samples));
+        self.state = TrainingState::Running;
+    }
+
+    /// Passes on the Train view's settings if they've changed.
+    pub fn update_settings(&mut self, settings: NetSettings) {
+        if settings != self.settings {
+            self.settings = settings;
+            self.send(Command::Settings(settings));
+        }
+    }
+
+    /// The messages that have arrived so far, without waiting for any more. `State` messages
+    /// are kept track of here, but passed on too.
+    pub fn messages(&mut self) -> Vec<Message> {
+        let messages: Vec<Message> = self.messages.try_iter().collect();
+
+        for message in &messages {
+            // the thread might not have seen the stop yet.
+            if let Message::State(state) = message {
+                if self.state != TrainingState::Stopping {
+                    self.state = *state;
+                }
+            }
+        }
+        messages
+    }
+
+    /// Whether the thread has ended, so every message it sent has arrived. It only ends
+    /// without `Message::Finished` if it panicked.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+}
+
+// what the trainers do next, once the GUI's commands have been handled.
+enum Next {
+    Train,
+    Pause,
+    Stop,
+    // new settings for the net; the trainer applies them when it can.
+    Settings(NetSettings),
+}
+
+// why a trainer can't carry on after a report.
+enum Halt {
+    Stop(StopReason),
+    // NaN or Inf turned up; the net is back at its last healthy weights, and should pause there.
+    Pause,
+}
+
+// the training thread's bookkeeping: commands from the GUI, reporting back to it, validation
+// and health checks.
+struct Run {
+    commands: Receiver<Command>,
+    // samples left to train on before pausing; paused at `Some(0)`.
+    budget: Option<usize>,
+    messages: Sender<Message>,
+    ctx: Arc<egui::Context>,
+    last_snapshot: Instant,
+    samples: usize,
+    // validation data is never trained on, so it makes a fair probe of the activations;
+    // without any, the training data has to do.
+    probe_data: Arc<Vec<NNData>>,
+    validation_data: Arc<Vec<NNData>>,
+    validation_interval: usize,
+    next_validation: usize,
+    stopper: Option<EarlyStopper>,
+    // the last weights without NaN/Inf, to go back to if training blows up.
+    last_healthy: NeuralNet,
+}
+
+impl Run {
+    fn send(&self, message: Message) {
+        // nobody's listening if the GUI has closed; the thread is told to stop then anyway.
+        let _ = self.messages.send(message);
+        self.ctx.request_repaint();
+    }
+
+    fn is_paused(&self) -> bool {
+        self.budget == Some(0)
+    }
+
+    // handles the GUI's commands. While paused, it waits (without spinning) for a command
+    // to carry on or stop. `Next::Pause` is only given when a command pauses training; see
+    // `paused`.
+    fn next(&mut self) -> Next {
+        loop {
+            let was_paused = self.is_paused();
+
+            let command = if was_paused {
+                match self.commands.recv() {
+                    Ok(command) => command,
+                    Err(_) => return Next::Stop,
+                }
+            } else {
+                match self.commands.try_recv() {
+                    Ok(command) => command,
+                    Err(TryRecvError::Empty) => return Next::Train,
+                    Err(TryRecvError::Disconnected) => return Next::Stop,
+                }
+            };
+
+            match command {
+                Command::Stop => return Next::Stop,
+                Command::Settings(settings) => return Next::Settings(settings),
+                Command::Pause if !was_paused => {
+                    self.budget = Some(0);
+                    return Next::Pause;
+                }
+                Command::Pause => {}
+                Command::Resume => self.budget = None,
+                Command::Train(samples) => self.budget = Some(samples.max(1)),
+            }
+
+            if was_paused && !self.is_paused() {
+                self.send(Message::State(TrainingState::Running));
+            }
+        }
+    }
+
+    // counts samples trained on against the budget; returns whether that used it up.
+    fn spend(&mut self, samples: usize) -> bool {
+        match &mut self.budget {
+            Some(left) => {
+                *left = left.saturating_sub(samples);
+                *left == 0
+            }
+            None => false,
+        }
+    }
+
+    // tells the GUI training has paused, with the weights it paused at.
+    fn paused(&mut self, nn: &NeuralNet) {
+        self.budget = Some(0);
+        self.send(Message::Snapshot(Box::new(nn.clone())));
+        self.send(Message::State(TrainingState::Paused));
+    }
+
+    // called with the losses of the samples trained on since the last report; says why to
+    // stop or pause, if training shouldn't carry on.
+    fn report(&mut self, nn: &mut NeuralNet, losses: &[f32]) -> Option<Halt> {
+        let problem = nn.find_non_finite(losses, &self.probe_data[0]);
+        self.send(Message::Losses(losses.iter().copied().take_while(|x| x.is_finite()).collect()));
+
+        if let Some(problem) = problem {
+            *nn = self.last_healthy.clone();
+            self.send(Message::NumericalError(problem));
+            return Some(Halt::Pause);
+        }
+        self.last_healthy = nn.clone();
+        self.samples += losses.len();
+
+        if self.samples >= self.next_validation && !self.validation_data.is_empty() {
+            self.next_validation = self.samples + self.validation_interval;
+
+            let evaluation = nn.evaluate(&self.validation_data);
+            self.send(Message::Validation(self.samples, evaluation));
+
+            if let Some(stop) = self.stopper.as_mut().and_then(|stopper| stopper.update(nn, &evaluation, self.samples)) {
+                return Some(Halt::Stop(StopReason::EarlyStop(stop)));
+            }
+        }
+
+        if self.last_snapshot.elapsed() >= SNAPSHOT_INTERVAL {
+            self.last_snapshot = Instant::now();
+            self.send(Message::Snapshot(Box::new(nn.clone())));
+        }
+        None
+    }
+}
+
+fn train_parallel(
+    nn: &mut NeuralNet,
+    training_data: Arc<Vec<NNData>>,
+    options: &TrainingOptions,
+    run: &mut Run,
+) -> StopReason {
+    let mut trainer = ParallelTrainer::new(options.threads, options.batch_size, options.seed);
+    let mut losses: Vec<f32> = Vec::with_capacity(LOSSES_PER_REPORT + options.batch_size);
+
+    // the trainer's workers share the net while they work out a batch's gradients; it's only
+    // changed in between, once they've let go of it, so `make_mut` never has to copy it.
+    let mut shared = Arc::new(nn.clone());
+
+    let reason = loop {
+        match run.next() {
+            Next::Train => {}
+            Next::Settings(settings) => {
+                settings.apply(Arc::make_mut(&mut shared));
+                continue;
+            }
+            Next::Pause => {
+                if let Some(Halt::Stop(reason)) = run.report(Arc::make_mut(&mut shared), &mem::take(&mut losses)) {
+                    break reason;
+                }
+                run.paused(&shared);
+                continue;
+            }
+            Next::Stop => {
+                run.send(Message::Losses(mem::take(&mut losses)));
+                break StopReason::Stopped;
+            }
+        }
+
+        let batch = trainer.next_batch(training_data.len());
+
+        if batch.len() == 1 {
+            // plain stochastic gradient descent.
+            losses.push(Arc::make_mut(&mut shared).train_one(&training_data[batch[0]]));
+        } else {
+            let (gradients, batch_losses) = trainer.compute_gradients(&shared, &training_data, &batch);
+            Arc::make_mut(&mut shared).apply_gradients(&gradients);
+            losses.extend(batch_losses);
+        }
+
+        let used_up = run.spend(batch.len());
+
+        // a NaN or infinite loss is reported straight away.
+        if used_up || losses.len() >= LOSSES_PER_REPORT || losses.iter().any(|x| !x.is_finite()) {
+            let halt = run.report(Arc::make_mut(&mut shared), &mem::take(&mut losses));
+            if let Some(Halt::Stop(reason)) = halt {
+                break reason;
+            }
+            if used_up || halt.is_some() {
+                run.paused(&shared);
+            }
+        }
+    };
+
+    *nn = Arc::unwrap_or_clone(shared);
+    reason
+}
+
+fn train_hogwild(
+    nn: &mut NeuralNet,
+    mut settings: NetSettings,
+    training_data: Arc<Vec<NNData>>,
+    options: &TrainingOptions,
+    run: &mut Run,
+) -> StopReason {
+    let (losses_tx, losses_rx) = mpsc::channel();
+    // the workers are stopped while paused, and started again (with new seeds) after.
+    let mut trainer: Option<HogwildTrainer> = None;
+    let mut seeds = StdRng::seed_from_u64(options.seed);
+    // losses from workers stopped for new settings, not reported yet.
+    let mut pending: Vec<f32> = Vec::new();
+
+    // takes the latest weights and losses from the workers; stops them if `stop`.
+    let collect = |nn: &mut NeuralNet, trainer: &mut Option<HogwildTrainer>, stop: bool| -> Vec<f32> {
+        if let Some(running) = trainer {
+            running.shared_weights().load_into(nn);
+            running.shared_weights().set_learning_rate(nn.learning_rate());
+            running.shared_weights().set_regularization(nn.regularization());
+            running.shared_weights().set_gradient_clipping(nn.gradient_clipping());
+        }
+        if stop {
+            if let Some(running) = trainer.take() {
+                running.stop();
+            }
+        }
+        losses_rx.try_iter().flatten().collect()
+    };
+
+    // the workers never touch `nn`; this thread just reports on them.
+    let reason = loop {
+        match run.next() {
+            Next::Train => {}
+            Next::Settings(new_settings) => {
+                // the workers' copies of the net (and the shared weights' layout) would no longer
+                // match, so they're stopped, and started again from the new net.
+                if new_settings.changes_layers(&settings) {
+                    pending.extend(collect(nn, &mut trainer, true));
+                }
+                // the learning rate, regularization and clipping reach the workers when collecting.
+                new_settings.apply(nn);
+                settings = new_settings;
+                continue;
+            }
+            Next::Pause => {
+                let mut losses = mem::take(&mut pending);
+                losses.extend(collect(nn, &mut trainer, true));
+                if let Some(Halt::Stop(reason)) = run.report(nn, &losses) {
+                    break reason;
+                }
+                run.paused(nn);
+                continue;
+            }
+            Next::Stop => {
+                let mut losses = mem::take(&mut pending);
+                losses.extend(collect(nn, &mut trainer, true));
+                run.send(Message::Losses(losses));
+                break StopReason::Stopped;
+            }
+        }
+
+        let running = trainer.get_or_insert_with(|| HogwildTrainer::start(
+            nn, Arc::clone(&training_data), options.threads, options.batch_size, None, seeds.random(), losses_tx.clone()));
+
+        thread::sleep(HOGWILD_REPORT_INTERVAL);
+
+        if running.is_finished() {
+            break StopReason::WorkersExited;
+        }
+
+        let mut losses = mem::take(&mut pending);
+        losses.extend(collect(nn, &mut trainer, false));
+        let used_up = run.spend(losses.len());
+        if used_up {
+            losses.extend(collect(nn, &mut trainer, true));
+        }
+
+        let halt = run.report(nn, &losses);
+        if let Some(Halt::Stop(reason)) = halt {
+            break reason;
+        }
+        if halt.is_some() {
+            // the shared weights are the broken ones; the workers start again from `nn` on resuming.
+            if let Some(running) = trainer.take() {
+                running.stop();
+            }
+            losses_rx.try_iter().for_each(drop);
+        }
+        if used_up || halt.is_some() {
+            run.paused(nn);
+        }
+    };
+
+    if let Some(running) = trainer {
+        running.stop();
+    }
+    reason
+}
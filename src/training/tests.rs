@@ -0,0 +1,208 @@
+use super::*;
+use crate::neural_net::tests::generate_learnable_data;
+
+use std::time::Duration;
+
+// long enough for a debug build on a busy machine; the tests only wait this long if they fail.
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+fn start(budget: Option<usize>, hogwild: bool) -> Training {
+    let options = TrainingOptions {
+        threads: 2,
+        batch_size: 5,
+        hogwild,
+        seed: 1,
+        validation_interval: 100,
+        early_stopping: None,
+        samples: 0,
+        budget,
+    };
+    Training::start(
+        NeuralNet::with_structure(vec![12, 8, 10]),
+        NetSettings::default(),
+        Arc::new(generate_learnable_data(200, 12, 2)),
+        Arc::new(generate_learnable_data(20, 12, 3)),
+        options,
+        Arc::new(egui::Context::default()))
+}
+
+// takes messages until one matches `last`, and gives all of them, that one included.
+fn messages_until(training: &mut Training, last: impl Fn(&Message) -> bool) -> Vec<Message> {
+    let start = Instant::now();
+    let mut received = Vec::new();
+
+    loop {
+        for message in training.messages() {
+            let done = last(&message);
+            received.push(message);
+            if done {
+                return received;
+            }
+        }
+        assert!(start.elapsed() < TIMEOUT, "timed out; got {received:?}");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn until_paused(training: &mut Training) -> Vec<Message> {
+    messages_until(training, |message| matches!(message, Message::State(TrainingState::Paused)))
+}
+
+fn losses(messages: &[Message]) -> usize {
+    messages.iter()
+        .map(|message| match message {
+            Message::Losses(losses) => losses.len(),
+            _ => 0,
+        })
+        .sum()
+}
+
+fn states(messages: &[Message]) -> Vec<TrainingState> {
+    messages.iter()
+        .filter_map(|message| match message {
+            Message::State(state) => Some(*state),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_train_pauses_after_the_samples_asked_for() {
+    let mut training = start(Some(50), false);
+
+    let messages = until_paused(&mut training);
+    assert_eq!(losses(&messages), 50);
+    assert_eq!(training.state(), TrainingState::Paused);
+
+    training.train(30);
+    // the GUI mustn't offer to start again before the thread has answered.
+    assert_eq!(training.state(), TrainingState::Running);
+
+    let messages = until_paused(&mut training);
+    assert_eq!(losses(&messages), 30);
+    assert_eq!(training.state(), TrainingState::Paused);
+
+    // a paused run has sent its weights as they are.
+    assert!(matches!(messages[messages.len() - 2], Message::Snapshot(_)));
+}
+
+#[test]
+fn test_pause_while_paused_does_nothing() {
+    let mut training = start(Some(10), false);
+    until_paused(&mut training);
+
+    training.pause();
+    training.pause();
+    thread::sleep(Duration::from_millis(200));
+    assert!(training.messages().is_empty());
+    assert_eq!(training.state(), TrainingState::Paused);
+
+    // and it still trains when asked to.
+    training.train(20);
+    let messages = until_paused(&mut training);
+    assert_eq!(losses(&messages), 20);
+    assert_eq!(states(&messages), vec![TrainingState::Running, TrainingState::Paused]);
+}
+
+#[test]
+fn test_stop_while_paused_ends_the_thread() {
+    let mut training = start(Some(10), false);
+    until_paused(&mut training);
+
+    training.stop();
+    assert_eq!(training.state(), TrainingState::Stopping);
+
+    let messages = messages_until(&mut training, |message| matches!(message, Message::Finished(_)));
+    assert!(matches!(messages.last(), Some(Message::Finished(StopReason::Stopped))));
+    // no late `State` takes it back out of stopping.
+    assert_eq!(training.state(), TrainingState::Stopping);
+
+    let start = Instant::now();
+    while !training.is_finished() {
+        assert!(start.elapsed() < TIMEOUT, "the thread didn't end");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn test_numerical_error_pauses_at_healthy_weights() {
+    for hogwild in [false, true] {
+        let mut training = start(None, hogwild);
+        let settings = NetSettings {
+            learning_rate: f32::INFINITY,
+            ..NetSettings::default()
+        };
+        training.update_settings(settings);
+
+        let messages = until_paused(&mut training);
+        assert!(messages.iter().any(|message| matches!(message, Message::NumericalError(_))), "hogwild: {hogwild}");
+        assert_eq!(training.state(), TrainingState::Paused, "hogwild: {hogwild}");
+        let Some(Message::Snapshot(nn)) = messages.iter().rev().find(|message| matches!(message, Message::Snapshot(_))) else {
+            panic!("no snapshot when paused; hogwild: {hogwild}");
+        };
+        assert_eq!(nn.find_non_finite(&[], &generate_learnable_data(1, 12, 4)[0]), None, "hogwild: {hogwild}");
+
+        // it can carry on from there.
+        training.update_settings(NetSettings::default());
+        training.train(20);
+        let messages = until_paused(&mut training);
+        assert_eq!(states(&messages), vec![TrainingState::Running, TrainingState::Paused], "hogwild: {hogwild}");
+
+        training.stop();
+        messages_until(&mut training, |message| matches!(message, Message::Finished(_)));
+    }
+}
+
+#[test]
+fn test_state_messages_arrive_in_order() {
+    for hogwild in [false, true] {
+        let mut training = start(Some(20), hogwild);
+        let mut received = until_paused(&mut training);
+
+        training.resume();
+        thread::sleep(Duration::from_millis(100));
+        training.pause();
+        received.extend(until_paused(&mut training));
+
+        training.train(20);
+        received.extend(until_paused(&mut training));
+
+        training.stop();
+        received.extend(messages_until(&mut training, |message| matches!(message, Message::Finished(_))));
+
+        assert_eq!(states(&received), vec![
+            TrainingState::Paused,
+            TrainingState::Running,
+            TrainingState::Paused,
+            TrainingState::Running,
+            TrainingState::Paused,
+        ], "hogwild: {hogwild}");
+    }
+}
+
+#[test]
+fn test_hogwild_takes_on_settings_that_change_the_layers() {
+    let mut training = start(None, true);
+    messages_until(&mut training, |message| matches!(message, Message::Losses(_)));
+
+    let settings = NetSettings {
+        batch_norm: true,
+        hidden_activation: Activation::Relu,
+        ..NetSettings::default()
+    };
+    training.update_settings(settings);
+    thread::sleep(Duration::from_millis(200));
+    messages_until(&mut training, |message| matches!(message, Message::Losses(_)));
+    training.pause();
+
+    let messages = until_paused(&mut training);
+    let Some(Message::Snapshot(nn)) = messages.iter().rev().find(|message| matches!(message, Message::Snapshot(_))) else {
+        panic!("no snapshot when paused");
+    };
+    assert!(nn.has_batch_norm(0));
+    assert_eq!(nn.activation(0), Some(Activation::Relu));
+
+    training.stop();
+    let messages = messages_until(&mut training, |message| matches!(message, Message::Finished(_)));
+    assert!(matches!(messages.last(), Some(Message::Finished(StopReason::Stopped))));
+}
@@ -0,0 +1,62 @@
+use eframe::egui::{ self, TextureHandle, TextureOptions };
+
+use crate::heatmap::{ self, TileGrid };
+use crate::neural_net::NeuralNet;
+
+const IMAGE_SIZE: [usize; 2] = [28, 28];
+const COLUMNS: usize = 16;
+
+/// The weights each unit of the first layer gives the pixels of the input image, as a
+/// heatmap per unit: red pixels push it up, blue ones down.
+#[derive(Default)]
+pub struct WeightsView {
+    /// Colour every tile on the same scale, rather than each on its own.
+    shared_scale: bool,
+    texture: Option<TextureHandle>,
+}
+
+impl WeightsView {
+    /// Drawn from `nn` every time, so it follows the weights as they're trained.
+    pub fn show(&mut self, ui: &mut egui::Ui, nn: &NeuralNet) {
+        let Some(layer) = nn.first_layer().filter(|layer| layer.inputs() == IMAGE_SIZE[0] * IMAGE_SIZE[1]) else {
+            ui.label("Only a net whose first layer is dense has weights on the pixels to show.");
+            return;
+        };
+
+        ui.checkbox(&mut self.shared_scale, "Same Scale for Every Unit");
+
+        let ranges: Vec<(f32, f32)> = layer.unit_weights()
+            .map(|weights| weights.iter().fold((f32::MAX, f32::MIN), |(min, max), &w| (min.min(w), max.max(w))))
+            .collect();
+        let largest = |(min, max): (f32, f32)| min.abs().max(max.abs()).max(f32::MIN_POSITIVE);
+        let shared = ranges.iter().copied().map(largest).fold(f32::MIN_POSITIVE, f32::max);
+
+        let grid = TileGrid { tile_size: IMAGE_SIZE, columns: COLUMNS, count: ranges.len() };
+        let image = grid.image(layer.unit_weights().zip(&ranges).map(|(weights, &range)| {
+            let scale = if self.shared_scale { shared } else { largest(range) };
+            weights.iter().map(|w| heatmap::diverging_color(w / scale)).collect()
+        }));
+
+        let texture = match &mut self.texture {
+            Some(texture) => {
+                texture.set(image, TextureOptions::NEAREST);
+                texture
+            }
+            None => self.texture.insert(ui.ctx().load_texture("first_layer_weights", image, TextureOptions::NEAREST)),
+        };
+
+        let response = ui.add(egui::Image::from_texture(&*texture)
+            .maintain_aspect_ratio(true)
+            .fit_to_fraction([1.0, 0.9].into())
+            .sense(egui::Sense::hover()));
+
+        let unit = response.hover_pos().and_then(|pos| {
+            let at = (pos - response.rect.min) / response.rect.size();
+            grid.tile_at([at.x, at.y])
+        });
+        if let Some(unit) = unit {
+            let (min, max) = ranges[unit];
+            response.on_hover_text_at_pointer(format!("unit {unit}\nweights {min:.3} to {max:.3}"));
+        }
+    }
+}